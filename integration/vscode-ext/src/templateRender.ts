@@ -10,6 +10,20 @@ import * as userConfig  from "./userConfig";
 import { RunnerOutputType, RunnerSubcommandVariant } from "./runner";
 import { RunnerState } from "./runnerState";
 
+/**
+ * Definition of an object containing details about a template variable.
+ * This is expected to be in the same format as what `devinit --list-vars --parsable` provides.
+ */
+export interface VariableDetail {
+    name: string,
+    description: string | null,
+    type: string | null,
+    default: any,
+    choices: any[],
+    required: boolean,
+    regex: string | null
+}
+
 /**
  * Render the file template with name `templateName` into the file at path `outputPath`, prompting the
  * user for the values of any variables not already contained in the `knownVariables` map.
@@ -28,7 +42,7 @@ export async function renderFileTemplatePrompted(
         : new Map();
 
     // query devinit for remaining needed variables in template `templateName`
-    let remainingVariables: string[];
+    let remainingVariables: VariableDetail[];
     try {
         remainingVariables = await listFileTemplateVars(runnerState, templateName, defaultVariablesMap);
     } catch (e) {
@@ -37,11 +51,21 @@ export async function renderFileTemplatePrompted(
 
     // query the user to specify each variable as necessary
     let definedVariables = new Map<string, string>();
-    for (const ident of remainingVariables) {
-        const value = await vscode.window.showInputBox({
-            title: `Define template variable \"${ident}\"`,
-            placeHolder: `What is \"${ident}\" equal to?`
-        });
+    for (const variable of remainingVariables) {
+        const ident = variable.name;
+        const value = (variable.choices.length > 0)
+            ? await vscode.window.showQuickPick(
+                variable.choices.map((c) => String(c)),
+                {
+                    title: `Define template variable \"${ident}\"`,
+                    placeHolder: variable.description ?? `What is \"${ident}\" equal to?`
+                })
+            : await vscode.window.showInputBox({
+                title: `Define template variable \"${ident}\"`,
+                prompt: variable.description ?? undefined,
+                placeHolder: `What is \"${ident}\" equal to?`,
+                value: (variable.default !== null) ? String(variable.default) : undefined
+            });
         // early return if any variables are skipped (i.e. input cancelled)
         if (value === undefined) {
             return Promise.reject("Input cancelled");
@@ -84,7 +108,7 @@ async function listFileTemplateVars(
     runnerState: RunnerState,
     templateName: string,
     knownVariables: Map<string, string>
-): Promise<string[]> {
+): Promise<VariableDetail[]> {
     let stdout, stderr;
     try {
         ({stdout, stderr} = await runnerState
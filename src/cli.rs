@@ -5,9 +5,10 @@
  *   See the LICENCE file for more information.
  */
 
-use std::error::Error;
-
 use clap::{Args, Parser, Subcommand};
+use tera::Value;
+
+use crate::{files::UndefinedPolicy, variables::parse_define};
 
 #[derive(Parser, Debug)]
 #[command(version = env!("DEVINITVERS"), about, long_about = None)]
@@ -19,6 +20,10 @@ pub struct Cli {
     #[arg(long)]
     pub config: Option<String>,
 
+    /// Select a named profile from the devinit configuration
+    #[arg(long, env = "DEVINIT_PROFILE")]
+    pub profile: Option<String>,
+
     /// Format stdout output to be easier to parse programatically
     #[arg(short, long)]
     pub parsable: bool,
@@ -29,6 +34,7 @@ pub enum CommandVariant {
     File(FileArgs),
     Project(ProjectArgs),
     List(ListArgs),
+    Config(ConfigArgs),
 }
 
 impl CommandVariant {
@@ -65,6 +71,10 @@ pub struct ProjectArgs {
     #[command(flatten)]
     pub output: OutputArgGroup,
 
+    /// Don't run the project template's hook commands (its check expressions are still evaluated)
+    #[arg(long)]
+    pub no_hooks: bool,
+
     #[command(flatten)]
     pub com: CommonArgGroup,
 }
@@ -73,6 +83,14 @@ pub struct ProjectArgs {
 #[derive(Args, Debug)]
 pub struct ListArgs {}
 
+/// Inspect the devinit configuration
+#[derive(Args, Debug)]
+pub struct ConfigArgs {
+    /// Print the effective configuration, merged from each config file found
+    #[arg(long, required = true)]
+    pub show: bool,
+}
+
 #[derive(Args, Debug)]
 #[group(required = true, multiple = false)]
 pub struct OutputArgGroup {
@@ -94,23 +112,28 @@ pub struct CommonArgGroup {
     /// The name of the template to use
     pub template: String,
 
-    /// Define variables to be substituted in the template
-    #[arg(short = 'D', number_of_values = 1, value_parser = parse_key_val::<String, String>)]
-    pub var_defines: Vec<(String, String)>,
+    /// Define variables to be substituted in the template, as KEY=value for a string or KEY:=value for a YAML/JSON value.
+    /// Dotted keys define fields of objects, and repeated keys define lists
+    #[arg(short = 'D', number_of_values = 1, value_parser = parse_define)]
+    pub var_defines: Vec<(String, Value)>,
+
+    /// Read variables from a YAML, JSON or TOML file. Variables defined with -D take priority
+    #[arg(long, value_name = "PATH")]
+    pub vars_file: Vec<String>,
+
+    /// After rendering, write the variables that were used to a YAML, JSON or TOML file, to be read with --vars-file.
+    /// Nothing is written with --dry-run or --list-vars
+    #[arg(long, value_name = "PATH")]
+    pub save_answers: Option<String>,
+
+    /// Never prompt for undefined variables, even when run in a terminal
+    #[arg(long)]
+    pub no_input: bool,
+
+    /// How to handle variables that are used by the template but not defined
+    #[arg(long, value_enum, value_name = "POLICY")]
+    pub undefined: Option<UndefinedPolicy>,
     // /// Print verbose output
     // #[arg(short, long)]
     // pub verbose: bool,
 }
-
-fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<dyn Error + Sync + Send>>
-where
-    T: std::str::FromStr,
-    T::Err: Error + Sync + Send + 'static,
-    U: std::str::FromStr,
-    U::Err: Error + Sync + Send + 'static,
-{
-    let pos = s
-        .find('=')
-        .ok_or_else(|| format!("invalid KEY=value: no `=` found in `{}`", s))?;
-    Ok((s[..pos].parse()?, s[pos + 1..].parse()?))
-}
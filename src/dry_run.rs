@@ -9,7 +9,9 @@ use std::{collections::HashMap, path};
 
 use colored::{ColoredString, Colorize};
 
-const INDENT_PREFIX: &'static str = "   | ";
+use crate::templater::{ProjectOutputContents, ProjectOutputFile};
+
+const INDENT_PREFIX: &str = "   | ";
 
 pub fn print_file_render<S: AsRef<str>>(name: S, render: S) {
     file_output(
@@ -24,22 +26,32 @@ pub fn print_file_render<S: AsRef<str>>(name: S, render: S) {
     );
 }
 
-pub fn print_project_render<S: AsRef<str>>(outputs: HashMap<S, S>) {
+pub fn print_project_render<S: AsRef<str>>(outputs: HashMap<S, ProjectOutputFile>) {
     // sort outputs by their path, first by alphabetical order and then by folders first
     let mut vec = outputs.into_iter().collect::<Vec<_>>();
     vec.sort_by_key(|p| p.0.as_ref().to_owned());
     vec.sort_by_key(|p| !p.0.as_ref().contains(path::is_separator));
 
-    for (output, render) in &vec {
+    for (output, file) in &vec {
         let (prefix, fname) = if let Some(pos) = output.as_ref().rfind("/") {
             (&output.as_ref()[..(pos + 1)], &output.as_ref()[(pos + 1)..])
         } else {
             ("", output.as_ref())
         };
 
+        let text = match &file.contents {
+            ProjectOutputContents::Text(s) => s.clone(),
+            ProjectOutputContents::Binary(b) => format!("<binary, {} bytes>", b.len()),
+        };
+
+        let mode = match file.mode {
+            Some(m) => format!(" ({m:04o})"),
+            None => String::new(),
+        };
+
         file_output(
-            format!("{}{}", prefix.green(), fname.green().bold()).into(),
-            render.as_ref().to_string(),
+            format!("{}{}{}", prefix.green(), fname.green().bold(), mode.green()).into(),
+            text,
         );
     }
 }
@@ -11,6 +11,7 @@ use std::process::exit;
 pub type DevinitResult<T> = Result<T, DevinitError>;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum DevinitError {
     FileReadWriteError(String),
     NoConfigError(),
@@ -20,6 +21,9 @@ pub enum DevinitError {
     TemplateRenderError(String),
     MissingProjectDirError(String),
     InvalidProjectConfigError(String),
+    InvalidVariablesError(String),
+    HookError(String),
+    PreHookFailedError(String),
 }
 
 impl DevinitError {
@@ -49,6 +53,15 @@ impl DevinitError {
             Self::InvalidProjectConfigError(s) => {
                 error!("Invalid or malformed project template config syntax: {s}\n");
             }
+            Self::InvalidVariablesError(s) => {
+                error!("Template variables failed validation:\n{s}\n");
+            }
+            Self::HookError(s) => {
+                error!("Project template hook failed: {s}\n");
+            }
+            Self::PreHookFailedError(s) => {
+                error!("Project template check failed, nothing was written: {s}\n");
+            }
         };
     }
 
@@ -75,6 +88,9 @@ impl From<&DevinitError> for i32 {
             DevinitError::TemplateRenderError(_) => 6,
             DevinitError::MissingProjectDirError(_) => 7,
             DevinitError::InvalidProjectConfigError(_) => 8,
+            DevinitError::InvalidVariablesError(_) => 9,
+            DevinitError::HookError(_) => 10,
+            DevinitError::PreHookFailedError(_) => 11,
         }
     }
 }
@@ -6,56 +6,292 @@
  */
 
 use crate::error::{DevinitError, DevinitResult};
+use clap::ValueEnum;
 use log::warn;
 use path_clean::PathClean;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::{
+    collections::BTreeMap,
     env, fs,
     path::{Path, PathBuf},
 };
+use tera::{Map, Value};
 
 /// Deserialized values as specified in the config YAML file.
 #[derive(Deserialize, Debug, Default, Clone)]
 pub struct ConfigYaml {
-    pub file_templates_loc: String,
-    pub project_templates_loc: String,
+    /// Settings given at the top level of the config file.
+    #[serde(flatten)]
+    pub settings: SettingsYaml,
+
+    /// Name of the profile to use if none is otherwise selected.
+    pub default_profile: Option<String>,
+
+    /// Named profiles, each overriding the top-level settings when selected.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, SettingsYaml>,
+}
+
+/// Deserialized settings that can be given either at the top level of the config YAML file, or in a profile.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct SettingsYaml {
+    /// File template locations, in order of descending priority.
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub file_templates_loc: Vec<String>,
+
+    /// Project template locations, in order of descending priority.
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub project_templates_loc: Vec<String>,
+
+    /// Default template variables, applied to every template.
+    #[serde(default)]
+    pub variables: Map<String, Value>,
+
+    /// Template-specific settings, keyed by template id.
+    #[serde(default)]
+    pub templates: BTreeMap<String, TemplateConfigYaml>,
+}
+
+/// Deserialized settings for a specific template, as specified in the config YAML file.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct TemplateConfigYaml {
+    /// Default template variables, overriding the global defaults.
+    #[serde(default)]
+    pub variables: Map<String, Value>,
+
+    /// How variables that are used by the template but not defined are handled.
+    pub undefined: Option<UndefinedPolicy>,
+}
+
+/// How variables that are used by a template but not defined are handled when rendering it.
+#[derive(Serialize, Deserialize, ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UndefinedPolicy {
+    /// Fail before rendering, listing every undefined variable
+    Error,
+    /// Render undefined variables as empty strings
+    Empty,
+    /// Leave the original `{{ expr }}` text of undefined variables in the output
+    Keep,
+}
+
+/// Deserialize a field that may either be a single string or a list of strings into a list.
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(s) => vec![s],
+        OneOrMany::Many(v) => v,
+    })
+}
+
+/// A configured value alongside the path of the config file that it was specified in.
+#[derive(Serialize, Debug, Clone)]
+pub struct Sourced<T> {
+    pub value: T,
+    pub origin: PathBuf,
+}
+
+/// The effective configuration, built by merging each config file that was found.
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct Config {
+    /// Absolute file template locations, in order of descending priority.
+    pub file_templates_loc: Vec<Sourced<PathBuf>>,
+
+    /// Absolute project template locations, in order of descending priority.
+    pub project_templates_loc: Vec<Sourced<PathBuf>>,
+
+    /// Default template variables, applied to every template.
+    pub variables: BTreeMap<String, Sourced<Value>>,
+
+    /// Default template variables for specific templates, keyed by template id.
+    pub template_variables: BTreeMap<String, BTreeMap<String, Sourced<Value>>>,
+
+    /// Undefined variable policies for specific templates, keyed by template id.
+    pub template_undefined: BTreeMap<String, Sourced<UndefinedPolicy>>,
+
+    /// Name of the profile that was applied, if any.
+    pub active_profile: Option<String>,
+}
+
+impl Config {
+    /// Get the default variables to be used when rendering the template with id `template`.
+    /// Template-specific variables take priority over global ones.
+    pub fn variables_for<S: AsRef<str>>(&self, template: S) -> Map<String, Value> {
+        let mut map = Map::new();
+        for (k, v) in &self.variables {
+            map.insert(k.clone(), v.value.clone());
+        }
+        if let Some(vars) = self.template_variables.get(template.as_ref()) {
+            for (k, v) in vars {
+                map.insert(k.clone(), v.value.clone());
+            }
+        }
+        map
+    }
+
+    /// Get the configured policy for undefined variables when rendering the template with id `template`, if any.
+    pub fn undefined_for<S: AsRef<str>>(&self, template: S) -> Option<UndefinedPolicy> {
+        self.template_undefined
+            .get(template.as_ref())
+            .map(|s| s.value)
+    }
+
+    /// Merge the settings deserialized into `yaml` over the current configuration.
+    /// `path` is the path of the config file they were specified in, to which template locations are relative.
+    fn merge(&mut self, yaml: SettingsYaml, path: &Path) {
+        let folder = path.parent().unwrap();
+        let resolve = |locs: Vec<String>| {
+            locs.into_iter()
+                .map(|l| Sourced {
+                    value: folder.join(l).clean(),
+                    origin: path.to_path_buf(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // locations in the newer config take priority over the existing ones
+        self.file_templates_loc = [
+            resolve(yaml.file_templates_loc),
+            std::mem::take(&mut self.file_templates_loc),
+        ]
+        .concat();
+        self.project_templates_loc = [
+            resolve(yaml.project_templates_loc),
+            std::mem::take(&mut self.project_templates_loc),
+        ]
+        .concat();
+
+        // variables in the newer config replace any existing ones with the same name
+        let sourced = |vars: Map<String, Value>| {
+            vars.into_iter().map(|(k, v)| {
+                (
+                    k,
+                    Sourced {
+                        value: v,
+                        origin: path.to_path_buf(),
+                    },
+                )
+            })
+        };
+        self.variables.extend(sourced(yaml.variables));
+        for (id, tpl) in yaml.templates {
+            if let Some(undefined) = tpl.undefined {
+                self.template_undefined.insert(
+                    id.clone(),
+                    Sourced {
+                        value: undefined,
+                        origin: path.to_path_buf(),
+                    },
+                );
+            }
+            if !tpl.variables.is_empty() {
+                self.template_variables
+                    .entry(id)
+                    .or_default()
+                    .extend(sourced(tpl.variables));
+            }
+        }
+    }
 }
 
 /// An intermediary builder struct for config deserialization.
 #[derive(Debug, Default, Clone)]
 pub struct ConfigYamlBuilder {
-    path: PathBuf,
-    path_parent: PathBuf,
+    /// Paths of each config file to read, in order of ascending priority
+    paths: Vec<PathBuf>,
 }
 
 impl ConfigYamlBuilder {
     /// Initialise a config yaml builder with an optional user-specified configuration path.
+    /// Any project-local config file, found in a `.devinit/` folder in the current directory or any of its parents, will
+    /// be merged over the user configuration.
     pub fn new<P: AsRef<Path>>(user_path: Option<P>) -> DevinitResult<Self> {
-        let path = Self::find_config(user_path)?.to_path_buf();
-        Ok(Self {
-            path: path.clone(),
-            path_parent: path.parent().unwrap().to_path_buf(),
-        })
+        let mut paths = vec![];
+
+        let user = Self::find_config(user_path)?;
+        if let Some(p) = &user {
+            paths.push(p.clone());
+        }
+        if let Some(p) = Self::find_local_config(user.as_deref())? {
+            paths.push(p);
+        }
+
+        if paths.is_empty() {
+            return Err(DevinitError::NoConfigError());
+        }
+
+        Ok(Self { paths })
     }
 
-    /// Load the config YAML file into a serializable ConfigYaml struct.
-    pub fn build(&self) -> DevinitResult<ConfigYaml> {
-        // load file
-        let file = fs::read_to_string(&self.path)
-            .map_err(|e| DevinitError::FileReadWriteError(e.to_string()))?;
+    /// Load each config YAML file, and merge them into a single Config struct.
+    /// The profile named `profile` is then applied over the result, or the configured default profile if `profile` is
+    /// `None`.
+    pub fn build<S: AsRef<str>>(&self, profile: Option<S>) -> DevinitResult<Config> {
+        let mut config = Config::default();
+
+        // each profile is made up of the settings for it in each config file
+        let mut profiles = BTreeMap::<String, Vec<(SettingsYaml, &PathBuf)>>::new();
+        let mut default_profile = None;
+
+        for path in &self.paths {
+            // load file
+            let file = fs::read_to_string(path)
+                .map_err(|e| DevinitError::FileReadWriteError(e.to_string()))?;
+
+            // deserialise
+            let yaml = serde_yaml::from_str::<ConfigYaml>(file.as_str())
+                .map_err(|e| DevinitError::InvalidConfigError(format!("{path:?}: {e}")))?;
+
+            config.merge(yaml.settings, path);
+            for (name, settings) in yaml.profiles {
+                profiles.entry(name).or_default().push((settings, path));
+            }
+            if yaml.default_profile.is_some() {
+                default_profile = yaml.default_profile;
+            }
+        }
+
+        // a profile given by the user takes priority over the configured default
+        let active_profile = profile.map(|p| p.as_ref().to_string()).or(default_profile);
 
-        // deserialise
-        serde_yaml::from_str::<ConfigYaml>(file.as_str())
-            .map_err(|e| DevinitError::InvalidConfigError(e.to_string()))
+        if let Some(name) = &active_profile {
+            let layers = profiles.remove(name).ok_or_else(|| {
+                DevinitError::InvalidConfigError(format!("No profile named \"{name}\" was found"))
+            })?;
+
+            // template locations set by the profile replace the top-level ones, rather than being added to them
+            if layers.iter().any(|(s, _)| !s.file_templates_loc.is_empty()) {
+                config.file_templates_loc.clear();
+            }
+            if layers
+                .iter()
+                .any(|(s, _)| !s.project_templates_loc.is_empty())
+            {
+                config.project_templates_loc.clear();
+            }
+
+            for (settings, path) in layers {
+                config.merge(settings, path);
+            }
+        }
+        config.active_profile = active_profile;
+
+        Ok(config)
     }
 
-    /// Return the path of the folder containing the config that is being read.
-    pub fn folder(&self) -> &PathBuf {
-        &self.path_parent
+    /// Return the paths of each config file that is being read, in order of ascending priority.
+    pub fn paths(&self) -> &Vec<PathBuf> {
+        &self.paths
     }
 
-    /// Find configuration file on disk, from a set of hard-coded locations **or** the user-specified path, if any.
-    fn find_config<P: AsRef<Path>>(user_path: Option<P>) -> DevinitResult<PathBuf> {
+    /// Find user configuration file on disk, from a set of hard-coded locations **or** the user-specified path, if any.
+    fn find_config<P: AsRef<Path>>(user_path: Option<P>) -> DevinitResult<Option<PathBuf>> {
         // first attempt user-specified path if applicable
         if let Some(p) = user_path {
             if p.as_ref()
@@ -63,16 +299,18 @@ impl ConfigYamlBuilder {
                 .map_err(|e| DevinitError::FileReadWriteError(e.to_string()))?
             {
                 // convert the user path to an absolute path (relative to cwd) if necessary
-                return Ok(if p.as_ref().is_absolute() {
-                    p.as_ref().to_path_buf()
-                } else {
-                    env::current_dir()
-                        .map_err(|_| {
-                            DevinitError::FileReadWriteError("Cwd not accessible".to_string())
-                        })?
-                        .join(p)
-                }
-                .clean());
+                return Ok(Some(
+                    if p.as_ref().is_absolute() {
+                        p.as_ref().to_path_buf()
+                    } else {
+                        env::current_dir()
+                            .map_err(|_| {
+                                DevinitError::FileReadWriteError("Cwd not accessible".to_string())
+                            })?
+                            .join(p)
+                    }
+                    .clean(),
+                ));
             } else {
                 warn!(
                     "While the --config flag was specified, its path could not be read: {:?}\n",
@@ -87,7 +325,7 @@ impl ConfigYamlBuilder {
             if p.try_exists()
                 .map_err(|e| DevinitError::FileReadWriteError(e.to_string()))?
             {
-                return Ok(p);
+                return Ok(Some(p));
             }
         }
 
@@ -97,10 +335,32 @@ impl ConfigYamlBuilder {
             if p.try_exists()
                 .map_err(|e| DevinitError::FileReadWriteError(e.to_string()))?
             {
-                return Ok(p);
+                return Ok(Some(p));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Walk up from the current working directory to find the nearest project-local config file, at
+    /// `.devinit/devinitrc.yml`. The user config at `user_path` is skipped if found, e.g. when walking through the home
+    /// directory.
+    fn find_local_config(user_path: Option<&Path>) -> DevinitResult<Option<PathBuf>> {
+        let cwd = env::current_dir()
+            .map_err(|_| DevinitError::FileReadWriteError("Cwd not accessible".to_string()))?;
+
+        for dir in cwd.ancestors() {
+            let p = dir.join(".devinit").join("devinitrc.yml");
+            if Some(p.as_path()) == user_path {
+                continue;
+            }
+            if p.try_exists()
+                .map_err(|e| DevinitError::FileReadWriteError(e.to_string()))?
+            {
+                return Ok(Some(p));
             }
         }
 
-        Err(DevinitError::NoConfigError())
+        Ok(None)
     }
 }
@@ -0,0 +1,69 @@
+/*
+ *   Copyright (c) 2024 Jack Bennett.
+ *   All Rights Reserved.
+ *
+ *   See the LICENCE file for more information.
+ */
+
+use serde::{Deserialize, Serialize};
+
+use super::{variable_decls, VariableDecl};
+
+/// The line that opens a front matter block.
+/// This is distinct from the usual `---`, so that templates of files with their own front matter (e.g. Jekyll pages)
+/// are left as they are.
+const FRONT_MATTER_OPENER: &str = "---devinit";
+
+/// The line that closes a front matter block.
+const FRONT_MATTER_DELIMITER: &str = "---";
+
+/// Metadata describing a template.
+/// For file templates, this is deserialized from the YAML front matter at the top of the template file.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateMeta {
+    /// Human-readable description of the template
+    pub description: Option<String>,
+
+    /// Tags to categorise the template by
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// Suggested filename to use when rendering into a directory
+    pub filename: Option<String>,
+
+    /// Language ids (as returned by `lang_by_filename`) that the template is intended for
+    #[serde(default)]
+    pub languages: Vec<String>,
+
+    /// Declarations of the variables used by the template, in the order they were declared
+    #[serde(default, deserialize_with = "variable_decls", skip_serializing)]
+    pub variables: Vec<VariableDecl>,
+}
+
+/// Split the YAML front matter block (if any) from the start of a template source, returning the deserialized metadata
+/// and the remaining template source.
+/// The front matter must begin on the first line of the source, opened by a line containing only `---devinit` and
+/// closed by a line containing only `---`.
+/// On failure, a description of the problem is returned.
+pub fn split_front_matter(source: &str) -> Result<(TemplateMeta, &str), String> {
+    let mut lines = source.split_inclusive('\n');
+    let start = match lines.next() {
+        Some(first) if first.trim_end() == FRONT_MATTER_OPENER => first.len(),
+        _ => return Ok((TemplateMeta::default(), source)),
+    };
+
+    // find the closing delimiter, keeping track of the byte offset of the end of the yaml between them
+    let mut end = start;
+    for line in lines {
+        if line.trim_end() == FRONT_MATTER_DELIMITER {
+            let meta = serde_yaml::from_str::<Option<TemplateMeta>>(&source[start..end])
+                .map_err(|e| format!("Invalid front matter: {e}"))?
+                .unwrap_or_default();
+            return Ok((meta, &source[(end + line.len())..]));
+        }
+        end += line.len();
+    }
+
+    Err("Front matter block was opened but never closed".to_string())
+}
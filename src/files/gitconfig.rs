@@ -0,0 +1,137 @@
+/*
+ *   Copyright (c) 2024 Jack Bennett.
+ *   All Rights Reserved.
+ *
+ *   See the LICENCE file for more information.
+ */
+
+use serde::Serialize;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// The git user identity, as configured in git config files.
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct GitUser {
+    /// Value of `user.name`, or empty if not configured
+    pub name: String,
+
+    /// Value of `user.email`, or empty if not configured
+    pub email: String,
+}
+
+impl GitUser {
+    /// Read the git user identity from the global git config files, and then the config of the repository containing
+    /// `dir` (if any), which takes priority.
+    /// Config files are read directly, without running git, and any that can't be read are skipped.
+    pub fn find<P: AsRef<Path>>(dir: P) -> Self {
+        let mut user = Self::default();
+
+        for path in Self::global_config_paths()
+            .into_iter()
+            .chain(Self::repo_config_path(dir.as_ref()))
+        {
+            if let Ok(s) = fs::read_to_string(&path) {
+                user.merge(&s);
+            }
+        }
+
+        user
+    }
+
+    /// Paths of the global git config files, in order of ascending priority.
+    fn global_config_paths() -> Vec<PathBuf> {
+        let mut paths = vec![];
+
+        // $XDG_CONFIG_HOME/git/config, defaulting to ~/.config/git/config
+        match env::var_os("XDG_CONFIG_HOME").filter(|x| !x.is_empty()) {
+            Some(xdg) => paths.push(PathBuf::from(xdg).join("git").join("config")),
+            None => {
+                if let Some(home) = dirs::home_dir() {
+                    paths.push(home.join(".config").join("git").join("config"));
+                }
+            }
+        }
+
+        // ~/.gitconfig
+        if let Some(home) = dirs::home_dir() {
+            paths.push(home.join(".gitconfig"));
+        }
+
+        paths
+    }
+
+    /// Find the config file of the git repository containing `dir`, by walking up to the nearest `.git` folder.
+    /// Linked worktrees and submodules, where `.git` is a file pointing to the real git directory, are followed.
+    fn repo_config_path(dir: &Path) -> Option<PathBuf> {
+        for d in dir.ancestors() {
+            let git = d.join(".git");
+            if git.is_dir() {
+                return Some(git.join("config"));
+            }
+            if git.is_file() {
+                let gitdir = fs::read_to_string(&git).ok()?;
+                let gitdir = d.join(gitdir.trim().strip_prefix("gitdir:")?.trim());
+
+                // linked worktrees keep their config in the main git directory
+                return Some(match fs::read_to_string(gitdir.join("commondir")) {
+                    Ok(common) => gitdir.join(common.trim()).join("config"),
+                    Err(_) => gitdir.join("config"),
+                });
+            }
+        }
+
+        None
+    }
+
+    /// Merge any `user.name` and `user.email` values in the git config file contents `s` over the current values.
+    fn merge(&mut self, s: &str) {
+        let mut in_user = false;
+
+        for line in s.lines().map(str::trim) {
+            if let Some(section) = line.strip_prefix('[') {
+                // section names are case-insensitive, and may have a subsection (e.g. `[remote "origin"]`)
+                let section = section.split(']').next().unwrap_or_default().trim();
+                in_user = section.eq_ignore_ascii_case("user");
+                continue;
+            }
+            if !in_user {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let key = key.trim();
+            if key.eq_ignore_ascii_case("name") {
+                self.name = parse_value(value);
+            } else if key.eq_ignore_ascii_case("email") {
+                self.email = parse_value(value);
+            }
+        }
+    }
+}
+
+/// Parse a git config value, removing quotes and trailing comments and resolving escape sequences.
+fn parse_value(value: &str) -> String {
+    let mut ret = String::new();
+    let mut quoted = false;
+    let mut chars = value.trim().chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' => match chars.next() {
+                Some('n') => ret.push('\n'),
+                Some('t') => ret.push('\t'),
+                Some(c) => ret.push(c),
+                None => {}
+            },
+            '#' | ';' if !quoted => break,
+            c => ret.push(c),
+        }
+    }
+
+    ret.trim().to_string()
+}
@@ -8,5 +8,11 @@
 mod devinitrc;
 pub use devinitrc::*;
 
+mod frontmatter;
+pub use frontmatter::*;
+
+mod gitconfig;
+pub use gitconfig::*;
+
 mod templaterc;
 pub use templaterc::*;
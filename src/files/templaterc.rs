@@ -6,18 +6,271 @@
  */
 
 use crate::error::{DevinitError, DevinitResult};
-use serde::Deserialize;
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::{gitignore::GitignoreBuilder, WalkBuilder};
+use log::warn;
+use serde::{de, Deserialize, Deserializer, Serialize};
 use std::{
-    collections::HashMap,
-    fs,
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
     path::{Path, PathBuf},
 };
+use tera::Value;
 
 /// Deserialized values as specified in the project template conf YAML file.
 #[derive(Deserialize, Debug, Default, Clone)]
 pub struct ProjectTemplateYaml {
-    /// Map where key is the input file and value is the output file
-    pub files: HashMap<String, String>,
+    /// Human-readable description of the template
+    pub description: Option<String>,
+
+    /// Tags to categorise the template by
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// Id of a project template to inherit files, variables and hooks from
+    pub extends: Option<String>,
+
+    /// Map where key is a subdirectory of the output and value is the id of a project template to emit into it
+    #[serde(default)]
+    pub include: BTreeMap<String, String>,
+
+    /// Map where key is the output file and value is the input file and its settings.
+    /// A null value removes a file inherited from the extended template.
+    #[serde(default)]
+    pub files: HashMap<String, Option<ProjectFileYaml>>,
+
+    /// Declarations of the variables used by the template, in the order they were declared
+    #[serde(default, deserialize_with = "variable_decls")]
+    pub variables: Vec<VariableDecl>,
+
+    /// Commands to run around generating the project
+    #[serde(default)]
+    pub hooks: HooksYaml,
+}
+
+/// Deserialized hook commands for a project template.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HooksYaml {
+    /// Checks run before any project files are written, in order
+    #[serde(default)]
+    pub pre: Vec<PreHookYaml>,
+
+    /// Commands run in the output directory after the project files are written, in order.
+    /// Each is a template rendered with the project variables.
+    #[serde(default)]
+    pub post: Vec<String>,
+}
+
+/// Deserialized settings for a single check run before a project is written.
+/// In the YAML file, these can either be given as a map, or as just a command to run.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(remote = "Self", deny_unknown_fields)]
+pub struct PreHookYaml {
+    /// Command to run, which passes if it exits successfully
+    pub run: Option<String>,
+
+    /// Tera expression, which passes if it evaluates to true, e.g. `name is matching("^[a-z_]+$")`
+    pub check: Option<String>,
+
+    /// Message to show if the check fails
+    pub message: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for PreHookYaml {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PreHookVisitor;
+
+        impl<'de> de::Visitor<'de> for PreHookVisitor {
+            type Value = PreHookYaml;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a command or a map of check settings")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(PreHookYaml {
+                    run: Some(v.to_string()),
+                    ..Default::default()
+                })
+            }
+
+            fn visit_map<M: de::MapAccess<'de>>(self, map: M) -> Result<Self::Value, M::Error> {
+                let hook = PreHookYaml::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                if hook.run.is_some() == hook.check.is_some() {
+                    return Err(de::Error::custom(
+                        "exactly one of `run` or `check` must be given",
+                    ));
+                }
+                Ok(hook)
+            }
+        }
+
+        deserializer.deserialize_any(PreHookVisitor)
+    }
+}
+
+/// Deserialized settings for a single file in a project template.
+/// In the YAML file, these can either be given as a map, or as just the input file path.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(remote = "Self", deny_unknown_fields)]
+pub struct ProjectFileYaml {
+    /// Path of the input file, relative to the project template folder.
+    /// This may also be a directory or a glob pattern (e.g. `skeleton/src/**`) matching several input files.
+    #[serde(default)]
+    pub source: String,
+
+    /// Id of a file template to render into the file, instead of an input file
+    pub file_template: Option<String>,
+
+    /// Glob patterns of files to leave out when `source` is a directory or glob, relative to its literal base
+    /// directory
+    #[serde(default)]
+    pub exclude: Vec<String>,
+
+    /// If true, the input file is copied byte-for-byte instead of being rendered as a template
+    #[serde(default, alias = "raw")]
+    pub copy: bool,
+
+    /// Unix permission mode to give the output file, e.g. `0755`.
+    /// If not given, the permission mode of the input file is used.
+    #[serde(default, deserialize_with = "octal_mode")]
+    pub mode: Option<u32>,
+
+    /// Template evaluated to decide if the file is emitted, e.g. `{{ use_ci }}`
+    pub when: Option<String>,
+
+    /// If true, the file is not emitted if its rendered contents are only whitespace
+    #[serde(default)]
+    pub skip_if_empty: bool,
+}
+
+impl<'de> Deserialize<'de> for ProjectFileYaml {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FileVisitor;
+
+        impl<'de> de::Visitor<'de> for FileVisitor {
+            type Value = ProjectFileYaml;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an input file path or a map of file settings")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(ProjectFileYaml {
+                    source: v.to_string(),
+                    ..Default::default()
+                })
+            }
+
+            fn visit_map<M: de::MapAccess<'de>>(self, map: M) -> Result<Self::Value, M::Error> {
+                let file =
+                    ProjectFileYaml::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                if file.source.is_empty() == file.file_template.is_none() {
+                    return Err(de::Error::custom(
+                        "exactly one of `source` or `file_template` must be given",
+                    ));
+                }
+                Ok(file)
+            }
+        }
+
+        deserializer.deserialize_any(FileVisitor)
+    }
+}
+
+/// The type of value that a declared template variable accepts.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VariableType {
+    String,
+    Bool,
+    Int,
+    List,
+    Choice,
+}
+
+/// A declaration of a template variable, describing what it means and which values it accepts.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct VariableDecl {
+    /// Variable identifier, taken from its key in the declarations map
+    #[serde(skip_deserializing)]
+    pub name: String,
+
+    /// Human-readable description of the variable
+    pub description: Option<String>,
+
+    /// The type of value accepted; if unspecified then any value is accepted
+    #[serde(rename = "type")]
+    pub ty: Option<VariableType>,
+
+    /// Value to use if the variable is not otherwise defined
+    pub default: Option<Value>,
+
+    /// If non-empty, the variable must be equal to one of these values
+    #[serde(default)]
+    pub choices: Vec<Value>,
+
+    /// If true, the variable must be defined (or have a default) for the template to be rendered
+    #[serde(default)]
+    pub required: bool,
+
+    /// Regular expression that string values must match
+    pub regex: Option<String>,
+}
+
+/// Deserialize a map of variable declarations into a list, preserving the order they were declared in.
+pub fn variable_decls<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<VariableDecl>, D::Error> {
+    struct DeclsVisitor;
+
+    impl<'de> de::Visitor<'de> for DeclsVisitor {
+        type Value = Vec<VariableDecl>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a map of variable names to declarations")
+        }
+
+        fn visit_map<M: de::MapAccess<'de>>(self, mut map: M) -> Result<Self::Value, M::Error> {
+            let mut ret = vec![];
+            while let Some((name, decl)) = map.next_entry::<String, Option<VariableDecl>>()? {
+                // a variable may be declared with no fields at all (e.g. `foo:`)
+                let mut decl = decl.unwrap_or_default();
+                decl.name = name;
+                ret.push(decl);
+            }
+            Ok(ret)
+        }
+    }
+
+    deserializer.deserialize_map(DeclsVisitor)
+}
+
+/// Deserialize a Unix permission mode, given either as a string (e.g. `"0755"`) or an integer.
+/// As YAML reads `0755` as the decimal integer 755, the digits of integers are also read as octal.
+fn octal_mode<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrInt {
+        String(String),
+        Int(u64),
+    }
+
+    let digits = match Option::<StringOrInt>::deserialize(deserializer)? {
+        Some(StringOrInt::String(s)) => s,
+        Some(StringOrInt::Int(i)) => i.to_string(),
+        None => return Ok(None),
+    };
+    let trimmed = digits.trim().trim_start_matches("0o");
+
+    match u32::from_str_radix(trimmed, 8) {
+        Ok(m) if m <= 0o7777 => Ok(Some(m)),
+        _ => Err(de::Error::invalid_value(
+            de::Unexpected::Str(&digits),
+            &"an octal permission mode such as 0755",
+        )),
+    }
 }
 
 /// An intermediary builder struct for config deserialization.
@@ -60,6 +313,122 @@ impl ProjectTemplateYamlBuilder {
             .map_err(|e| DevinitError::InvalidConfigError(e.to_string()))
     }
 
+    /// Expand any directory and glob entries in `files` into one entry per matched input file, each with its source
+    /// and output path relative to the entry's own. Null entries are left out.
+    /// Files matched by the `.devinitignore` file in the template folder are skipped, in addition to the files that
+    /// configure the template itself. Entries naming a single file are kept as they are.
+    pub fn expand_files(
+        &self,
+        files: HashMap<String, Option<ProjectFileYaml>>,
+    ) -> DevinitResult<Vec<(String, ProjectFileYaml)>> {
+        let invalid = |e: String| {
+            DevinitError::InvalidProjectConfigError(format!("{}: {e}", self.path.display()))
+        };
+
+        // a missing ignore file just means that nothing is ignored
+        let mut ignore = GitignoreBuilder::new(&self.path_parent);
+        let ignore_path = self.path_parent.join(IGNORE_FILE_NAME);
+        if ignore_path.is_file() {
+            if let Some(e) = ignore.add(ignore_path) {
+                return Err(invalid(e.to_string()));
+            }
+        }
+        let ignore = ignore.build().map_err(|e| invalid(e.to_string()))?;
+
+        let mut ret = vec![];
+        for (out, file) in files {
+            // entries that only remove inherited files have nothing to expand
+            let Some(file) = file else {
+                continue;
+            };
+            if file.file_template.is_some() {
+                ret.push((out, file));
+                continue;
+            }
+
+            let mut pattern = file.source.trim_end_matches('/').to_string();
+            if !is_glob(&pattern) {
+                if !self.path_parent.join(&pattern).is_dir() {
+                    ret.push((out, file));
+                    continue;
+                }
+                pattern.push_str("/**");
+            }
+
+            // the literal directory containing every match, to which output paths are relative
+            let base = pattern
+                .split('/')
+                .take_while(|c| !is_glob(c))
+                .collect::<Vec<_>>()
+                .join("/");
+            // the output directory to mirror into, e.g. `src` for `src/**`
+            let mut out_dir = out.trim_end_matches('/').split('/').collect::<Vec<_>>();
+            while out_dir.last().is_some_and(|c| c.contains('*')) {
+                out_dir.pop();
+            }
+            let out_dir = out_dir.join("/");
+            if out_dir.contains('*') {
+                return Err(invalid(format!(
+                    "Output path \"{out}\" may only contain wildcards in its final components"
+                )));
+            }
+
+            let matcher = GlobBuilder::new(&pattern)
+                .literal_separator(true)
+                .build()
+                .map_err(|e| invalid(e.to_string()))?
+                .compile_matcher();
+            let exclude = build_glob_set(&file.exclude).map_err(|e| invalid(e.to_string()))?;
+
+            let mut matched = vec![];
+            let walk = WalkBuilder::new(self.path_parent.join(&base))
+                .standard_filters(false)
+                .sort_by_file_name(|a, b| a.cmp(b))
+                .build();
+            for entry in walk {
+                let entry = entry.map_err(|e| DevinitError::FileReadWriteError(e.to_string()))?;
+                let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+                let rel = entry.path().strip_prefix(&self.path_parent).unwrap();
+                if is_dir
+                    || Some(rel) == self.path.strip_prefix(&self.path_parent).ok()
+                    || rel == Path::new(IGNORE_FILE_NAME)
+                    || !matcher.is_match(rel)
+                    || ignore.matched_path_or_any_parents(rel, false).is_ignore()
+                {
+                    continue;
+                }
+
+                let rel_base = rel.strip_prefix(&base).unwrap();
+                if exclude.is_match(rel_base) {
+                    continue;
+                }
+
+                let rel_base = rel_base.to_string_lossy().replace('\\', "/");
+                let path = if out_dir.is_empty() {
+                    rel_base
+                } else {
+                    format!("{out_dir}/{rel_base}")
+                };
+                matched.push((
+                    path,
+                    ProjectFileYaml {
+                        source: rel.to_string_lossy().to_string(),
+                        exclude: vec![],
+                        ..file.clone()
+                    },
+                ));
+            }
+
+            if matched.is_empty() {
+                warn!("\"{}\" in {:?} matched no files", file.source, self.path);
+            }
+            ret.append(&mut matched);
+        }
+
+        ret.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(ret)
+    }
+
     /// Return the path of the folder containing the project template config file.
     pub fn folder(&self) -> &PathBuf {
         &self.path_parent
@@ -70,3 +439,21 @@ impl ProjectTemplateYamlBuilder {
         &self.name
     }
 }
+
+/// Name of the file in the template folder listing, in gitignore syntax, input files to leave out of directory and
+/// glob entries
+const IGNORE_FILE_NAME: &str = ".devinitignore";
+
+/// Check if a path contains any glob metacharacters.
+fn is_glob(path: &str) -> bool {
+    path.contains(['*', '?', '[', '{'])
+}
+
+/// Compile a list of glob patterns into a single set.
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for p in patterns {
+        builder.add(Glob::new(p)?);
+    }
+    builder.build()
+}
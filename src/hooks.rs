@@ -0,0 +1,49 @@
+/*
+ *   Copyright (c) 2024 Jack Bennett.
+ *   All Rights Reserved.
+ *
+ *   See the LICENCE file for more information.
+ */
+
+use std::{path::Path, process::Command};
+
+use crate::error::{DevinitError, DevinitResult};
+
+/// Run the shell command `cmd` in the directory `dir`, failing if it does not exit successfully.
+/// The command inherits devinit's standard streams, so its output is shown to the user, and is given the path of the
+/// project output directory in the `DEVINIT_OUTPUT_DIR` environment variable.
+pub fn run_hook<P: AsRef<Path>, Q: AsRef<Path>>(
+    cmd: &str,
+    dir: P,
+    output_dir: Q,
+) -> DevinitResult<()> {
+    let status = shell(cmd)
+        .current_dir(dir)
+        .env("DEVINIT_OUTPUT_DIR", output_dir.as_ref())
+        .status()
+        .map_err(|e| DevinitError::HookError(format!("\"{cmd}\" could not be run: {e}")))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(DevinitError::HookError(format!(
+            "\"{cmd}\" failed ({status})"
+        )))
+    }
+}
+
+/// Build a command to run `cmd` with the platform's shell.
+#[cfg(windows)]
+fn shell(cmd: &str) -> Command {
+    let mut c = Command::new("cmd");
+    c.args(["/C", cmd]);
+    c
+}
+
+/// Build a command to run `cmd` with the platform's shell.
+#[cfg(not(windows))]
+fn shell(cmd: &str) -> Command {
+    let mut c = Command::new("sh");
+    c.args(["-c", cmd]);
+    c
+}
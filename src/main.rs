@@ -9,21 +9,24 @@ use clap::Parser;
 use cli::{Cli, CommandVariant, OutputArgGroup};
 use colored::Colorize;
 use error::{DevinitError, DevinitResult};
-use files::{ConfigYaml, ConfigYamlBuilder};
+use files::{Config, ConfigYamlBuilder, Sourced, UndefinedPolicy, VariableDecl};
+use log::warn;
 use path_clean::PathClean;
+use serde::Serialize;
 use std::{
-    collections::HashMap,
-    env,
-    ffi::OsStr,
-    fs,
-    io::{ErrorKind, Read, Write},
-    path::{Path, PathBuf},
+    collections::BTreeMap,
+    env, fs,
+    io::{self, ErrorKind, IsTerminal, Read, Write},
+    path::{is_separator, Path, PathBuf},
     process::exit,
 };
 use templater::{
-    get_missing_template_vars, BuiltinVariables, RendererVariant, Template, TemplateSet,
-    BUILTIN_VARIABLES_IDENT,
+    check_value, get_missing_template_vars, replace_undefined_vars, validate_variables,
+    BuiltinVariables, ContextArcMutex, PreHookOutcome, RendererVariant, Template, TemplateListing,
+    TemplateSet,
 };
+use tera::{Map, Value};
+use variables::{merge_vars, save_vars, vars_from_defines, vars_from_env, vars_from_file};
 
 use crate::templater::Renderer;
 
@@ -31,8 +34,10 @@ mod cli;
 mod dry_run;
 mod error;
 mod files;
+mod hooks;
 mod logger;
 mod templater;
+mod variables;
 
 fn main() {
     logger::init_logger(false); // hard-coding verbosity to false for now since there's currently no need for a verbose flag
@@ -41,25 +46,36 @@ fn main() {
         let args = Cli::parse();
 
         let config_builder = ConfigYamlBuilder::new(args.config.as_deref())?;
-        let config = config_builder.build()?;
+        let config = config_builder.build(args.profile.as_deref())?;
+
+        // if the config subcommand is specified, then show the configuration and return early.
+        if let CommandVariant::Config(_) = args.subcommand {
+            show_config(&config_builder, &config, args.parsable);
+            return Ok(());
+        }
 
         // load templates from configured paths
-        let template_set = load_template_set(&config_builder, &config)?;
+        let template_set = load_template_set(&config)?;
 
         // if the list subcommand is specified, then list them and return early.
         if let CommandVariant::List(_) = args.subcommand {
-            list_templates(&template_set, args.parsable);
+            list_templates(
+                &template_set,
+                config.active_profile.as_deref(),
+                args.parsable,
+            );
             return Ok(());
         }
 
         // build rendering context from command-line arguments
-        let (mut renderer, output_conf, assert_empty) = match args.subcommand {
+        let (mut renderer, output_conf, assert_empty, run_hooks) = match args.subcommand {
             CommandVariant::File(ref args) => (
                 template_set
                     .get_file_template(&args.com.template)?
                     .make_renderer()?,
                 &args.output,
                 args.assert_empty,
+                false,
             ),
             CommandVariant::Project(ref args) => (
                 template_set
@@ -67,33 +83,46 @@ fn main() {
                     .make_renderer()?,
                 &args.output,
                 false,
+                !args.no_hooks,
             ),
             _ => panic!("Invalid subcommand found, unexpected behaviour"),
         };
 
-        // get vars that were recieved from the command line
-        let cli_var_defs = args
-            .subcommand
-            .get_common_args()
-            .var_defines
-            .clone()
-            .into_iter()
-            .collect::<HashMap<_, _>>();
+        // get default vars from the config, then vars from any variables files, then vars from the environment, and then
+        // vars that were recieved from the command line (each taking priority over the last)
+        let common_args = args.subcommand.get_common_args();
+        let mut var_defs = config.variables_for(&common_args.template);
+        for path in &common_args.vars_file {
+            merge_vars(&mut var_defs, vars_from_file(path)?);
+        }
+        merge_vars(&mut var_defs, vars_from_env()?);
+        merge_vars(&mut var_defs, vars_from_defines(&common_args.var_defines)?);
 
         // if the --list-vars option is provided, list them and return early
         if output_conf.list_vars {
-            // get all variables referenced in the to-be-rendered template and filter out the ones specified on the cli
-            // this results in a list of completely undefined variable identifiers
-            let undefined_vars = find_referenced_vars(&renderer)?
-                .into_iter()
-                .filter(|v| !cli_var_defs.contains_key(v))
-                .collect::<Vec<_>>();
-
             // only list variables that are still undefined
-            list_variables(&undefined_vars, args.parsable);
+            list_variables(&find_undefined_vars(&renderer, &var_defs)?, args.parsable);
             return Ok(());
         }
 
+        // ask for any undefined variables if there is a user at a terminal to answer
+        if !common_args.no_input && io::stdin().is_terminal() {
+            prompt_variables(&find_undefined_vars(&renderer, &var_defs)?, &mut var_defs)?;
+        }
+
+        // check defined variables against those declared by the template, and fill in any defaults
+        validate_variables(renderer.variable_decls(), &mut var_defs)?;
+
+        // handle any variables that are still undefined, if a policy for them is given
+        // this is applied to a copy, so that the empty values it may define aren't saved as answers
+        let mut render_vars = var_defs.clone();
+        if let Some(policy) = common_args
+            .undefined
+            .or(config.undefined_for(&common_args.template))
+        {
+            apply_undefined_policy(&mut renderer, &mut render_vars, policy)?;
+        }
+
         // make output path absolute if it was specified
         let output_path_absolute = if let Some(path) = &output_conf.path {
             let path = PathBuf::from(&path);
@@ -101,11 +130,7 @@ fn main() {
                 if path.is_absolute() {
                     path
                 } else {
-                    env::current_dir()
-                        .map_err(|_| {
-                            DevinitError::FileReadWriteError("Cwd not accessible".to_string())
-                        })?
-                        .join(path)
+                    current_dir()?.join(path)
                 }
                 .clean()
                 .display()
@@ -115,13 +140,47 @@ fn main() {
             None
         };
 
+        // if rendering a file template into a directory, use the template's suggested filename
+        let output_path_absolute = match (&renderer, output_path_absolute) {
+            (RendererVariant::File(f), Some(path))
+                if Path::new(&path).is_dir()
+                    || output_conf
+                        .path
+                        .as_ref()
+                        .is_some_and(|p| p.ends_with(is_separator)) =>
+            {
+                let filename = f.template().meta().filename.as_ref().ok_or_else(|| {
+                    DevinitError::FileReadWriteError(format!(
+                        "Output path {path} is a directory, and template \"{}\" does not suggest a filename",
+                        f.template().name()
+                    ))
+                })?;
+                Some(Path::new(&path).join(filename).display().to_string())
+            }
+            (_, path) => path,
+        };
+
         let output_conf = OutputArgGroup {
             path: output_path_absolute, // use absolute path
             dry_run: output_conf.dry_run,
             list_vars: output_conf.list_vars,
         };
 
-        render(&mut renderer, &cli_var_defs, &output_conf, assert_empty)?;
+        render(
+            &mut renderer,
+            &render_vars,
+            &output_conf,
+            assert_empty,
+            run_hooks,
+        )?;
+
+        // save the variables used for the render, so that it can be repeated with --vars-file
+        // nothing is written on a dry run, answers included
+        if !output_conf.dry_run {
+            if let Some(path) = &common_args.save_answers {
+                save_vars(&var_defs, path)?;
+            }
+        }
 
         Ok(())
     }() {
@@ -132,20 +191,20 @@ fn main() {
 }
 
 /// Load a set of templates as specified in the provided configuration.
-/// `cfg_builder` is also required as it contains the folder containing the config file, to which template locations
-/// are relative.
-fn load_template_set<'a>(
-    cfg_builder: &ConfigYamlBuilder,
-    cfg: &ConfigYaml,
-) -> DevinitResult<TemplateSet<'a>> {
-    let template_paths = vec![
-        cfg_builder.folder().join(&cfg.file_templates_loc),
-        cfg_builder.folder().join(&cfg.project_templates_loc),
-    ];
-
-    Ok(TemplateSet::new()
-        .load_file_templates(&template_paths[0])?
-        .load_project_templates(&template_paths[1])?)
+fn load_template_set<'a>(cfg: &Config) -> DevinitResult<TemplateSet<'a>> {
+    let mut set = TemplateSet::new();
+
+    // locations are configured in order of descending priority, but the template set must be given them in ascending
+    // order so that templates in higher-priority locations shadow the others
+    for loc in cfg.file_templates_loc.iter().rev() {
+        set = set.load_file_templates(&loc.value)?;
+    }
+    for loc in cfg.project_templates_loc.iter().rev() {
+        set = set.load_project_templates(&loc.value)?;
+    }
+
+    // project templates can only be composed once all of them are loaded
+    set.compose_project_templates()
 }
 
 /// Build a list of all variables referenced in the template to be rendered by `renderer`.
@@ -154,31 +213,166 @@ fn find_referenced_vars(renderer: &RendererVariant) -> DevinitResult<Vec<String>
     // get the names of the variables needed for the template render
     // they are 'remaining' as they
     let mut ret = vec![];
+    let (context, names) = rendered_template_names(renderer);
+    for name in names {
+        ret.append(&mut get_missing_template_vars(context.clone(), name)?);
+    }
+
+    Ok(ret)
+}
+
+/// Get the ids of each Tera template rendered by `renderer`, along with the context that they are stored in.
+fn rendered_template_names(renderer: &RendererVariant) -> (ContextArcMutex, Vec<String>) {
     match renderer {
-        RendererVariant::File(ref f) => ret.append(&mut get_missing_template_vars(
-            f.template().context(),
-            f.template().name(),
-        )?),
+        RendererVariant::File(ref f) => (renderer.context(), vec![f.template().name().clone()]),
         RendererVariant::Project(ref p) => {
-            for name in p.template().file_template_names() {
-                ret.append(&mut get_missing_template_vars(
-                    p.template().context(),
+            // variables may be referenced in output paths, conditions and hooks as well as in file contents
+            let files = p.template().files().iter().flat_map(|f| f.template_names());
+            let pre_hooks = p
+                .template()
+                .pre_hooks()
+                .iter()
+                .flat_map(|h| h.template_names());
+            (
+                renderer.context(),
+                files
+                    .chain(pre_hooks)
+                    .chain(p.template().post_hooks())
+                    .cloned()
+                    .collect(),
+            )
+        }
+    }
+}
+
+/// Apply the policy `policy` for variables that are used by the template to be rendered by `renderer`, but are not
+/// defined in `var_map`.
+fn apply_undefined_policy(
+    renderer: &mut RendererVariant,
+    var_map: &mut Map<String, Value>,
+    policy: UndefinedPolicy,
+) -> DevinitResult<()> {
+    let mut undefined = find_referenced_vars(renderer)?;
+    undefined.sort();
+    undefined.dedup();
+    undefined.retain(|v| !var_map.contains_key(v));
+
+    match policy {
+        UndefinedPolicy::Error if !undefined.is_empty() => {
+            return Err(DevinitError::InvalidVariablesError(
+                undefined
+                    .iter()
+                    .map(|v| format!("  - \"{v}\" is used by the template but was not defined"))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ));
+        }
+        UndefinedPolicy::Error => {}
+        UndefinedPolicy::Empty | UndefinedPolicy::Keep => {
+            // the templates are rewritten in a copy of their context, as other templates may be rendered with them
+            let (_, names) = rendered_template_names(renderer);
+            let context = renderer.detach_context();
+            for name in names {
+                replace_undefined_vars(
+                    context.clone(),
                     name,
-                )?);
+                    var_map,
+                    policy == UndefinedPolicy::Keep,
+                )?;
             }
         }
-    };
+    }
 
-    Ok(ret)
+    // undefined variables may also be used outside of variable blocks and conditions, e.g. in set blocks
+    if policy == UndefinedPolicy::Empty {
+        for v in undefined {
+            var_map.insert(v, Value::from(""));
+        }
+    }
+
+    Ok(())
+}
+
+/// Build a list of the variables used by the template to be rendered by `renderer` that are not defined in `var_map`.
+/// This starts with the template's declared variables and then adds any other variables referenced in the template, so
+/// that declared information is included where available.
+fn find_undefined_vars(
+    renderer: &RendererVariant,
+    var_map: &Map<String, Value>,
+) -> DevinitResult<Vec<VariableDecl>> {
+    let mut undefined_vars = renderer.variable_decls().clone();
+    let mut referenced = find_referenced_vars(renderer)?;
+    referenced.sort();
+    referenced.dedup();
+    for v in referenced {
+        if !undefined_vars.iter().any(|d| d.name == v) {
+            undefined_vars.push(VariableDecl {
+                name: v,
+                ..Default::default()
+            });
+        }
+    }
+    undefined_vars.retain(|d| !var_map.contains_key(&d.name));
+
+    Ok(undefined_vars)
+}
+
+/// Ask the user for the value of each variable in `variables`, inserting their answers into `var_map`.
+/// Answers are checked against the variable's declaration, and asked for again if they are invalid. An empty answer
+/// uses the declared default, or otherwise leaves the variable undefined.
+fn prompt_variables(
+    variables: &[VariableDecl],
+    var_map: &mut Map<String, Value>,
+) -> DevinitResult<()> {
+    let read_err =
+        |e| DevinitError::FileReadWriteError(format!("Failed to read answer from stdin: {e}"));
+
+    for var in variables {
+        eprintln!("{}", describe_variable(var));
+        loop {
+            eprint!("{} ", ">".bold());
+            io::stderr().flush().map_err(read_err)?;
+
+            let mut answer = String::new();
+            if io::stdin().read_line(&mut answer).map_err(read_err)? == 0 {
+                // stdin was closed, so there are no more answers
+                eprintln!();
+                return Ok(());
+            }
+
+            let answer = answer.trim_end_matches(['\n', '\r']);
+            let val = match (answer, &var.default) {
+                ("", Some(default)) => default.clone(),
+                ("", None) if var.required => {
+                    eprintln!("{}", format!("\"{}\" is required", var.name).red());
+                    continue;
+                }
+                ("", None) => break,
+                (answer, _) => Value::from(answer),
+            };
+
+            match check_value(var, val) {
+                Ok(v) => {
+                    var_map.insert(var.name.clone(), v);
+                    break;
+                }
+                Err(e) => eprintln!("{}", format!("\"{}\" {e}", var.name).red()),
+            }
+        }
+    }
+
+    Ok(())
 }
 
 /// Invoke the render() function on the specified renderer, with different behaviour depending on the renderer variant.
 /// `var_map` is used to provide variable context, and output type and location depends on `output`.
-fn render<S: AsRef<str>>(
+/// Project template hook commands are only run if `run_hooks` is true, and never on a dry run.
+fn render(
     renderer: &mut RendererVariant,
-    var_map: &HashMap<S, S>,
+    var_map: &Map<String, Value>,
     output: &OutputArgGroup,
     assert_empty: bool,
+    run_hooks: bool,
 ) -> DevinitResult<()> {
     let mut builtins = BuiltinVariables::default();
 
@@ -187,15 +381,31 @@ fn render<S: AsRef<str>>(
         RendererVariant::File(ref mut f) => {
             // set up built-in variables if using --path=<p>
             if let Some(p) = &output.path {
-                (
-                    builtins.file_name,
-                    builtins.parent_name,
-                    builtins.file_contents,
-                ) = get_file_builtin_info(Path::new(p))?;
+                builtins = BuiltinVariables::for_output_path(p)?;
             }
+            builtins.load_environment(match &output.path {
+                Some(p) => Path::new(p).parent().unwrap_or(Path::new(p)).to_path_buf(),
+                None => current_dir()?,
+            });
             f.set_builtin_variables(&builtins);
 
-            // add user state (CLI-defined variables)
+            // warn if the output file doesn't seem to be in any of the languages the template is intended for
+            let languages = &f.template().meta().languages;
+            if !builtins.file_name.is_empty() && !languages.is_empty() {
+                let lang = match builtins.lang.as_str() {
+                    "" => "unknown",
+                    l => l,
+                };
+                if !languages.iter().any(|l| l == lang) {
+                    warn!(
+                        "Template \"{}\" is intended for languages [{}], but the output file appears to be \"{lang}\"\n",
+                        f.template().name(),
+                        languages.join(", ")
+                    );
+                }
+            }
+
+            // add user state (config and CLI-defined variables)
             for (k, v) in var_map {
                 f.add_variable(k, v);
             }
@@ -207,7 +417,7 @@ fn render<S: AsRef<str>>(
                 dry_run::print_file_render(name, &f);
             } else {
                 let path = output.path.as_ref().unwrap();
-                match fs::File::options().write(true).read(true).open(&path) {
+                match fs::File::options().write(true).read(true).open(path) {
                     Ok(mut file) => {
                         // the file exists so check for --assert-empty; if it is not empty then return early
                         if assert_empty {
@@ -219,7 +429,7 @@ fn render<S: AsRef<str>>(
                                 ))
                             })?;
 
-                            if read.trim().len() > 0 {
+                            if !read.trim().is_empty() {
                                 println!(
                                     "File at {} contains content, aborting (--assert-empty)",
                                     &path
@@ -238,7 +448,7 @@ fn render<S: AsRef<str>>(
                     Err(err) => {
                         if err.kind() == ErrorKind::NotFound {
                             // if the file doesn't already exist then create it and write the render output into it
-                            fs::write(&path, &f).map_err(|e| {
+                            fs::write(path, &f).map_err(|e| {
                                 DevinitError::FileReadWriteError(format!(
                                     "Failed to write file to {}: {e}",
                                     &path
@@ -257,22 +467,52 @@ fn render<S: AsRef<str>>(
         }
         // a project template was specified (devinit project)...
         RendererVariant::Project(ref mut p) => {
-            // TODO: builtin variables for project templates
-            //       note that path-specific builtins are a pain because we have to process them all for each
-            //       output file *before* rendering.
-
-            // add user state (CLI-defined variables)
+            // add user state (config and CLI-defined variables)
             for (k, v) in var_map {
                 p.add_variable(k, v);
             }
 
-            let p = p.render()?;
+            // built-in variables are extended for each output file when rendering, as some depend on its path
+            builtins.load_environment(match &output.path {
+                Some(path) => PathBuf::from(path),
+                None => current_dir()?,
+            });
+            p.set_builtin_variables(&builtins);
+            if let Some(path) = &output.path {
+                p.set_output_dir(path);
+            }
+
+            // post hooks are rendered up front so a broken command fails before anything is written
+            let files = p.render()?;
+            let post_hooks = p.render_post_hooks()?;
+
+            // run checks before anything is written
+            // expressions are always checked, but commands are only run if hooks are enabled and files would be written
+            for outcome in p.render_pre_hooks()? {
+                match outcome {
+                    PreHookOutcome::Passed => {}
+                    PreHookOutcome::Failed(msg) => {
+                        return Err(DevinitError::PreHookFailedError(msg))
+                    }
+                    PreHookOutcome::Command(_, _) if !run_hooks || output.dry_run => {}
+                    PreHookOutcome::Command(cmd, msg) => {
+                        // keep the reason the command failed, e.g. its exit status, alongside the message
+                        hooks::run_hook(&cmd, current_dir()?, output.path.as_ref().unwrap())
+                            .map_err(|e| match e {
+                                DevinitError::HookError(reason) => {
+                                    DevinitError::PreHookFailedError(format!("{msg}: {reason}"))
+                                }
+                                e => e,
+                            })?;
+                    }
+                }
+            }
 
             if output.dry_run {
-                dry_run::print_project_render(p);
+                dry_run::print_project_render(files);
             } else {
-                for (pat, txt) in &p {
-                    let pat = PathBuf::from(&output.path.as_ref().unwrap()).join(&pat);
+                for (pat, file) in &files {
+                    let pat = PathBuf::from(&output.path.as_ref().unwrap()).join(pat);
                     let dir = pat.parent().unwrap();
 
                     fs::create_dir_all(dir).map_err(|e| {
@@ -281,11 +521,23 @@ fn render<S: AsRef<str>>(
                         ))
                     })?;
 
-                    fs::write(&pat, &txt).map_err(|e| {
+                    fs::write(&pat, file.contents.as_bytes()).map_err(|e| {
                         DevinitError::FileReadWriteError(format!(
                             "Failed to write file to {pat:?}: {e}"
                         ))
                     })?;
+
+                    if let Some(mode) = file.mode {
+                        set_file_mode(&pat, mode)?;
+                    }
+                }
+
+                // run hooks in the project directory once all of its files are written
+                if run_hooks {
+                    let dir = Path::new(output.path.as_ref().unwrap());
+                    for cmd in post_hooks {
+                        hooks::run_hook(&cmd, dir, dir)?;
+                    }
                 }
             }
         }
@@ -294,134 +546,279 @@ fn render<S: AsRef<str>>(
     Ok(())
 }
 
-/// Print a pretty-formatted list of templates available on the system.
-fn list_templates(templates: &TemplateSet, parsable: bool) {
-    // function to print a single list item (template)
-    fn print_tpl_brief<'a, T: Template<'a>>(t: &&T, parsable: bool, add_comma: bool) {
-        if parsable {
-            // JSON format
-            print!(
-                "{{\"name\":\"{}\",\"source\":\"{}\"}}{}",
-                t.name(),
-                t.source(),
-                if add_comma { "," } else { "" }
-            );
-        } else {
-            println!(
-                "  - {} {}",
-                t.name().green().bold(),
-                format!("({})", t.source()).dimmed()
-            );
-        }
-    }
+/// Get the current working directory.
+fn current_dir() -> DevinitResult<PathBuf> {
+    env::current_dir()
+        .map_err(|_| DevinitError::FileReadWriteError("Cwd not accessible".to_string()))
+}
 
-    let mut ft = templates.get_file_templates_all();
-    ft.sort();
-    let mut pt = templates.get_project_templates_all();
-    pt.sort();
+/// Set the Unix permission mode of the file at `path`.
+#[cfg(unix)]
+fn set_file_mode(path: &Path, mode: u32) -> DevinitResult<()> {
+    use std::os::unix::fs::PermissionsExt;
 
-    let mut i = 0;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).map_err(|e| {
+        DevinitError::FileReadWriteError(format!(
+            "Failed to set permissions of file at {path:?}: {e}"
+        ))
+    })
+}
 
-    // start of file templates
-    if parsable {
-        print!("{{\"file\":[");
-    } else {
-        println!("{}", "File templates:".bold());
+/// Permission modes are only supported on Unix, so they are ignored elsewhere.
+#[cfg(not(unix))]
+fn set_file_mode(_path: &Path, _mode: u32) -> DevinitResult<()> {
+    Ok(())
+}
+
+/// Print a pretty-formatted list of templates available on the system, grouped by the location they were found in.
+fn list_templates(templates: &TemplateSet, profile: Option<&str>, parsable: bool) {
+    // function to print a single list item (template) in JSON format
+    fn print_tpl_brief_json<'a, T: Template<'a>>(l: &TemplateListing<T>, add_comma: bool) {
+        // template metadata (description, tags, etc) is included alongside the template's details
+        let mut obj = match tera::to_value(l.template.meta()).unwrap() {
+            Value::Object(o) => o,
+            _ => Map::new(),
+        };
+        obj.insert("name".to_string(), l.template.name().as_str().into());
+        obj.insert("source".to_string(), l.template.source().as_str().into());
+        obj.insert(
+            "location".to_string(),
+            l.location.display().to_string().into(),
+        );
+
+        print!("{}{}", Value::Object(obj), if add_comma { "," } else { "" });
     }
-    if ft.len() > 0 {
-        for t in &ft {
-            print_tpl_brief(t, parsable, i < (ft.len() - 1));
-            i += 1;
+
+    // function to print each list item, either in JSON format or by location
+    fn print_tpl_list<'a, T: Template<'a>>(listings: &[TemplateListing<T>], parsable: bool) {
+        if parsable {
+            for (i, l) in listings.iter().enumerate() {
+                print_tpl_brief_json(l, i < listings.len() - 1);
+            }
+            return;
         }
-    } else {
-        if !parsable {
-            println!("{}", "  No file templates found".red());
+
+        // listings are already ordered by location priority
+        let mut locations = listings.iter().map(|l| l.location).collect::<Vec<_>>();
+        locations.dedup();
+
+        for loc in locations {
+            println!("  {}", loc.display().to_string().dimmed());
+            for l in listings.iter().filter(|l| l.location == loc) {
+                let source = Path::new(l.template.source());
+                let source = source.strip_prefix(loc).unwrap_or(source);
+                if l.shadowed {
+                    println!(
+                        "    - {} {} {}",
+                        l.template.name().strikethrough(),
+                        format!("({})", source.display()).dimmed(),
+                        "(shadowed)".yellow()
+                    );
+                } else {
+                    print!(
+                        "    - {} {}",
+                        l.template.name().green().bold(),
+                        format!("({})", source.display()).dimmed()
+                    );
+                    if let Some(desc) = &l.template.meta().description {
+                        print!(": {desc}");
+                    }
+                    if !l.template.meta().tags.is_empty() {
+                        print!(
+                            " {}",
+                            format!("[{}]", l.template.meta().tags.join(", ")).cyan()
+                        );
+                    }
+                    println!();
+                }
+            }
         }
     }
 
-    i = 0;
+    let ft = templates.get_file_template_listings();
+    let pt = templates.get_project_template_listings();
 
-    // start of project templates
     if parsable {
+        let (ft, ft_shadowed): (Vec<_>, Vec<_>) = ft.into_iter().partition(|l| !l.shadowed);
+        let (pt, pt_shadowed): (Vec<_>, Vec<_>) = pt.into_iter().partition(|l| !l.shadowed);
+
+        // shadowed templates are given separately so that the main lists only contain usable templates
+        print!(
+            "{{\"profile\":{},\"file\":[",
+            profile.map(json_str).unwrap_or("null".to_string())
+        );
+        print_tpl_list(&ft, true);
         print!("],\"project\":[");
+        print_tpl_list(&pt, true);
+        print!("],\"shadowed\":{{\"file\":[");
+        print_tpl_list(&ft_shadowed, true);
+        print!("],\"project\":[");
+        print_tpl_list(&pt_shadowed, true);
+        println!("]}}}}");
+        return;
+    }
+
+    if let Some(profile) = profile {
+        println!("{} {}", "Active profile:".bold(), profile.green().bold());
+    }
+
+    // start of file templates
+    println!("{}", "File templates:".bold());
+    if !ft.is_empty() {
+        print_tpl_list(&ft, false);
     } else {
-        println!("{}", "Project templates:".bold());
+        println!("{}", "  No file templates found".red());
     }
-    if pt.len() > 0 {
-        for t in &pt {
-            print_tpl_brief(t, parsable, i < (pt.len() - 1));
-            i += 1;
-        }
+
+    // start of project templates
+    println!("{}", "Project templates:".bold());
+    if !pt.is_empty() {
+        print_tpl_list(&pt, false);
     } else {
-        if !parsable {
-            println!("{}", "  No project templates found".red());
+        println!("{}", "  No project templates found".red());
+    }
+}
+
+/// Print the effective configuration, and the config file that each setting came from.
+fn show_config(cfg_builder: &ConfigYamlBuilder, cfg: &Config, parsable: bool) {
+    // function to print a list of sourced paths under a heading
+    fn print_paths(heading: &str, list: &[Sourced<PathBuf>]) {
+        println!("{}", heading.bold());
+        if list.is_empty() {
+            println!("{}", "  None configured".red());
+        }
+        for s in list {
+            println!(
+                "  - {} {}",
+                s.value.display().to_string().green().bold(),
+                format!("(from {})", s.origin.display()).dimmed()
+            );
+        }
+    }
+
+    // function to print a map of sourced variables, with the given indentation
+    fn print_vars(vars: &BTreeMap<String, Sourced<Value>>, indent: &str) {
+        for (k, s) in vars {
+            println!(
+                "{indent}- {} = {} {}",
+                k.blue().bold(),
+                s.value,
+                format!("(from {})", s.origin.display()).dimmed()
+            );
         }
     }
 
     if parsable {
-        println!("]}}");
+        #[derive(Serialize)]
+        struct Shown<'b> {
+            files: &'b Vec<PathBuf>,
+            #[serde(flatten)]
+            config: &'b Config,
+        }
+
+        println!(
+            "{}",
+            tera::to_value(Shown {
+                files: cfg_builder.paths(),
+                config: cfg,
+            })
+            .unwrap()
+        );
+        return;
     }
+
+    println!(
+        "{}",
+        "Config files (in order of ascending priority):".bold()
+    );
+    for p in cfg_builder.paths() {
+        println!("  - {}", p.display());
+    }
+    if let Some(profile) = &cfg.active_profile {
+        println!("{} {}", "Active profile:".bold(), profile.green().bold());
+    }
+    print_paths("File template locations:", &cfg.file_templates_loc);
+    print_paths("Project template locations:", &cfg.project_templates_loc);
+
+    println!("{}", "Variables:".bold());
+    if cfg.variables.is_empty() {
+        println!("{}", "  None configured".red());
+    }
+    print_vars(&cfg.variables, "  ");
+
+    for (id, vars) in &cfg.template_variables {
+        println!("{}", format!("Variables for template {id}:").bold());
+        print_vars(vars, "  ");
+    }
+
+    for (id, s) in &cfg.template_undefined {
+        println!(
+            "{} {} {}",
+            format!("Undefined variables for template {id}:").bold(),
+            tera::to_value(s.value)
+                .unwrap()
+                .as_str()
+                .unwrap()
+                .green()
+                .bold(),
+            format!("(from {})", s.origin.display()).dimmed()
+        );
+    }
+}
+
+/// Format the given string as a quoted and escaped JSON string.
+fn json_str<S: AsRef<str>>(s: S) -> String {
+    tera::Value::from(s.as_ref()).to_string()
 }
 
-/// Print a pretty-formatted list of variables (i.e. where they must be defined in order to render a template)
-fn list_variables(variables: &Vec<String>, parsable: bool) {
+/// Print a pretty-formatted list of variables (i.e. where they must be defined in order to render a template), along
+/// with any declared information about them
+fn list_variables(variables: &[VariableDecl], parsable: bool) {
     if parsable {
-        let mut i = 0;
-
-        print!("[");
-        for var in variables {
-            print!(
-                "\"{}\"{}",
-                var,
-                if i < (variables.len() - 1) { "," } else { "" }
-            );
-            i += 1;
-        }
-        println!("]");
-    } else {
-        println!("{}", "Remaining variables:".bold());
-        for var in variables {
-            println!("  - {}", var.blue().bold());
-        }
+        println!("{}", tera::to_value(variables).unwrap());
+        return;
+    }
+
+    println!("{}", "Remaining variables:".bold());
+    for var in variables {
+        println!("  - {}", describe_variable(var));
     }
 }
 
-/// Return the filename, file contents (if the path exists - otherwise empty str), etc of the given path.
-/// This is intended to be used to retrieve file-related contents for the BUILTIN variables.
-fn get_file_builtin_info<P: AsRef<Path>>(path: P) -> DevinitResult<(String, String, String)> {
-    Ok((
-        // path file name
-        path.as_ref()
-            .file_name()
-            .and_then(OsStr::to_str)
-            .ok_or(DevinitError::FileReadWriteError(format!(
-                "Output file path {:?} is not valid UTF-8",
-                path.as_ref()
-            )))?
-            .to_owned(),
-        // path parent directory name
-        path.as_ref()
-            .parent()
-            .unwrap_or(PathBuf::from("").as_path())
-            .file_name()
-            .and_then(OsStr::to_str)
-            .ok_or(DevinitError::FileReadWriteError(format!(
-                "Path of parent directory of output file {:?} is not valid UTF-8",
-                path.as_ref()
-            )))?
-            .to_string(),
-        // file contents
-        match fs::read_to_string(&path) {
-            Ok(c) => c,
-            Err(e) => {
-                if e.kind() == ErrorKind::NotFound {
-                    String::from("")
-                } else {
-                    return Err(DevinitError::FileReadWriteError(format!(
-                        "When attempting to read to {BUILTIN_VARIABLES_IDENT}.file_contents: {e}",
-                    )));
-                }
-            }
-        },
-    ))
+/// Describe a declared variable on a single line, for listing or prompting.
+fn describe_variable(var: &VariableDecl) -> String {
+    let mut attrs = vec![];
+    if let Some(ty) = var.ty {
+        attrs.push(tera::to_value(ty).unwrap().as_str().unwrap().to_owned());
+    }
+    if var.required {
+        attrs.push("required".to_owned());
+    }
+
+    let mut ret = var.name.blue().bold().to_string();
+    if !attrs.is_empty() {
+        ret += &format!(" {}", format!("({})", attrs.join(", ")).dimmed());
+    }
+    if let Some(desc) = &var.description {
+        ret += &format!(": {desc}");
+    }
+    if let Some(default) = &var.default {
+        ret += &format!(" {}", format!("[default: {default}]").dimmed());
+    }
+    if !var.choices.is_empty() {
+        ret += &format!(
+            " {}",
+            format!(
+                "[choices: {}]",
+                var.choices
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+            .dimmed()
+        );
+    }
+
+    ret
 }
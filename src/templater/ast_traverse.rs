@@ -5,17 +5,22 @@
  *   See the LICENCE file for more information.
  */
 
-use std::vec;
+use std::{collections::HashMap, vec};
 
 use super::{ContextArcMutex, BUILTIN_VARIABLES_IDENT};
 use crate::error::{DevinitError, DevinitResult};
 use tera::{
-    ast::{Expr, ExprVal, Node},
-    Template, Tera,
+    ast::{
+        Block, Expr, ExprVal, FunctionCall, In, LogicExpr, MacroCall, MacroDefinition, MathExpr,
+        Node, WS,
+    },
+    Map, Template, Tera, Value,
 };
 
-/// Get all variables in the template with id `tpl_name` (and any templates in its 'include' tree) that do
-/// not have a matching 'set' block (i.e. are missing, unless specified with the -D cli flag)
+/// Get all variables that the template with id `tpl_name` takes from the render context (i.e. are missing, unless
+/// specified with the -D cli flag), including those used by any templates that it extends, includes or imports macros
+/// from.
+/// Names bound by the template itself, with set blocks, for loops and macro parameters, are not included.
 pub fn get_missing_template_vars<S: AsRef<str>>(
     context: ContextArcMutex,
     tpl_name: S,
@@ -23,79 +28,570 @@ pub fn get_missing_template_vars<S: AsRef<str>>(
     let context = context.lock().unwrap();
     let tera = context.tera();
 
-    let templates = get_templates_recursive(&tera, &tpl_name)?;
+    let template = get_template(tera, tpl_name.as_ref())?;
 
-    // we will get variable references ('gets') and set expressions ('sets')
-    // then find any gets without a matching set, i.e. wholly undefined.
-    let mut get_vars = vec![];
-    let mut set_vars = vec![];
+    // a template that extends another is rendered as its furthest parent, using the blocks that it overrides
+    let root = match template.parents.last() {
+        Some(parent) => get_template(tera, parent)?,
+        None => template,
+    };
 
-    for template in &templates {
-        // get variable reference nodes.
-        // identifier exprvals will be unwrapped to get underlying variable names as strings
-        get_vars.append(
-            &mut find_all_matches(&template, |n| matches!(n, Node::VariableBlock(_, _)))?
-                .into_iter()
-                .filter_map(|n| {
-                    if let Node::VariableBlock(_, expr) = n {
-                        // get required variable identifiers in expr
-                        // here we also filter out any references to BUILTINs, since we know they are defined by the renderer
-                        Some(
-                            get_expr_variable_idents(&expr, true)
-                                .into_iter()
-                                .map(|v| v[0])
-                                .collect::<Vec<&str>>(),
-                        )
-                    } else {
-                        None
+    let mut walk = VarWalk {
+        tera,
+        frames: vec![vec![]],
+        missing: vec![],
+        blocks: &template.blocks_definitions,
+        including: vec![&root.name],
+        imported: vec![],
+    };
+
+    // macros defined or imported anywhere in the inheritance chain can be used when rendering
+    for name in std::iter::once(&template.name).chain(template.parents.iter()) {
+        if name != &root.name {
+            for node in &get_template(tera, name)?.ast {
+                if matches!(node, Node::MacroDefinition(..) | Node::ImportMacro(..)) {
+                    walk.node(node)?;
+                }
+            }
+        }
+    }
+    walk.nodes(&root.ast)?;
+
+    let mut ret = walk
+        .missing
+        .into_iter()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    ret.sort();
+
+    Ok(ret)
+}
+
+/// A walk over the abstract syntax trees of templates, in the order that they are rendered, which finds the variables
+/// that they take from the render context.
+struct VarWalk<'a> {
+    tera: &'a Tera,
+
+    /// Names bound by the templates being walked, in a stack of frames (e.g. one per for loop)
+    frames: Vec<Vec<&'a str>>,
+
+    /// Variables used without being bound, in the order they were found
+    missing: Vec<&'a str>,
+
+    /// Definitions of each block in the rendered template and its parents, keyed by block name
+    blocks: &'a HashMap<String, Vec<(String, Block)>>,
+
+    /// Ids of the templates currently being walked, to avoid infinitely recursive includes
+    including: Vec<&'a str>,
+
+    /// Ids of the templates that macros were imported from
+    imported: Vec<&'a str>,
+}
+
+impl<'a> VarWalk<'a> {
+    fn nodes(&mut self, nodes: &'a [Node]) -> DevinitResult<()> {
+        for node in nodes {
+            self.node(node)?;
+        }
+        Ok(())
+    }
+
+    fn node(&mut self, node: &'a Node) -> DevinitResult<()> {
+        match node {
+            Node::VariableBlock(_, expr) => self.expr(expr),
+            Node::Set(_, set) => {
+                self.expr(&set.value);
+
+                // set_global binds the name outside of any for loops
+                let frame = if set.global {
+                    self.frames.first_mut()
+                } else {
+                    self.frames.last_mut()
+                };
+                frame.unwrap().push(&set.key);
+            }
+            Node::If(stat, _) => {
+                for (_, cond, body) in &stat.conditions {
+                    self.expr(cond);
+                    self.nodes(body)?;
+                }
+                if let Some((_, body)) = &stat.otherwise {
+                    self.nodes(body)?;
+                }
+            }
+            Node::Forloop(_, stat, _) => {
+                self.expr(&stat.container);
+
+                let mut frame = vec![stat.value.as_str(), "loop"];
+                frame.extend(stat.key.as_deref());
+                self.frames.push(frame);
+                self.nodes(&stat.body)?;
+                self.frames.pop();
+
+                // the fallback body is rendered outside of the loop
+                if let Some(body) = &stat.empty_body {
+                    self.nodes(body)?;
+                }
+            }
+            Node::FilterSection(_, sect, _) => {
+                for arg in sect.filter.args.values() {
+                    self.expr(arg);
+                }
+                self.nodes(&sect.body)?;
+            }
+            Node::Block(_, block, _) => {
+                // a block may be overridden, and each definition can render the one it overrides with super()
+                match self.blocks.get(&block.name) {
+                    Some(defs) => {
+                        for (_, def) in defs {
+                            self.nodes(&def.body)?;
+                        }
                     }
-                })
-                .collect(),
-        );
+                    None => self.nodes(&block.body)?,
+                }
+            }
+            Node::MacroDefinition(_, def, _) => self.macro_definition(def)?,
+            Node::Include(_, ids, ignore_missing) => {
+                // the first of the given templates that exists is included, and can use names bound so far
+                match ids.iter().find_map(|id| self.tera.templates.get(id)) {
+                    Some(t) if !self.including.contains(&t.name.as_str()) => {
+                        self.including.push(&t.name);
+                        self.nodes(&t.ast)?;
+                        self.including.pop();
+                    }
+                    Some(_) => {}
+                    None if *ignore_missing => {}
+                    None => return Err(DevinitError::IdNotFoundError(ids.join(", "))),
+                }
+            }
+            Node::ImportMacro(_, id, _) if !self.imported.contains(&id.as_str()) => {
+                self.imported.push(id);
+                for node in &get_template(self.tera, id)?.ast {
+                    if matches!(node, Node::MacroDefinition(..) | Node::ImportMacro(..)) {
+                        self.node(node)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Walk the body of a macro, which can only use its own parameters and the render context.
+    fn macro_definition(&mut self, def: &'a MacroDefinition) -> DevinitResult<()> {
+        for default in def.args.values().flatten() {
+            self.expr(default);
+        }
+
+        let params = def.args.keys().map(String::as_str).collect();
+        let frames = std::mem::replace(&mut self.frames, vec![params]);
+        let ret = self.nodes(&def.body);
+        self.frames = frames;
+
+        ret
+    }
+
+    /// Record any variables used by `expr` that have not been bound.
+    fn expr(&mut self, expr: &'a Expr) {
+        for id in get_expr_variable_idents(expr, true) {
+            let bound = self.frames.iter().flatten().any(|n| *n == id[0]);
+            if !bound && !self.missing.contains(&id[0]) {
+                self.missing.push(id[0]);
+            }
+        }
+    }
+}
+
+/// Get the template with id `tpl_name`.
+fn get_template<'a>(tera: &'a Tera, tpl_name: &str) -> DevinitResult<&'a Template> {
+    tera.templates
+        .get(tpl_name)
+        .ok_or(DevinitError::IdNotFoundError(tpl_name.to_string()))
+}
+
+/// Replace each variable block in the template with id `tpl_name` (and any templates in its 'include' tree) that
+/// references a variable not defined in `vars`, so that rendering it does not fail.
+/// If `keep` is true, then each block is replaced with its own `{{ expr }}` source text, and otherwise it is removed.
+/// See `replace_undefined_in_nodes` for how if and for tags are handled.
+///
+/// The templates are modified in place, so `context` should only be used for the render that needs them.
+pub fn replace_undefined_vars<S: AsRef<str>>(
+    context: ContextArcMutex,
+    tpl_name: S,
+    vars: &Map<String, Value>,
+    keep: bool,
+) -> DevinitResult<()> {
+    let mut context = context.lock().unwrap();
 
-        // same deal for Set expressions, in which variable values are set.
-        // again, variable ident strings (in this case, keys) are retrieved:
-        set_vars.append(
-            &mut find_all_matches(&template, |n| matches!(n, Node::Set(_, _)))?
+    // the templates are collected first, as they can't be modified while borrowed by the traversal
+    // variables set anywhere in the template tree are taken to be defined, as they may be set before an include
+    let mut names = vec![];
+    let mut scope = vec![];
+    for template in get_templates_recursive(context.tera(), &tpl_name)? {
+        names.push(template.name.clone());
+        scope.extend(
+            find_all_matches(template, |n| matches!(n, Node::Set(_, _)))?
                 .into_iter()
-                .filter_map(|n| {
-                    // extract variable identifier from node, if available
-                    if let Node::Set(_, set) = n {
-                        get_vars.push(
-                            get_expr_variable_idents(&set.value, true)
-                                .into_iter()
-                                .map(|v| v[0])
-                                .collect::<Vec<&str>>(),
-                        );
-
-                        Some(&set.key)
-                    } else {
-                        None
-                    }
-                })
-                .collect(),
+                .filter_map(|n| match n {
+                    Node::Set(_, set) => Some((set.key.clone(), true)),
+                    _ => None,
+                }),
         );
     }
 
-    // since get_vars is a list of identifier lists, we flatten it into just being a list of identifiers
-    let mut get_vars = get_vars.into_iter().flatten().collect::<Vec<&str>>();
-    get_vars.sort();
-    get_vars.dedup();
+    for name in names {
+        let template = context.tera_mut().templates.get_mut(&name).unwrap();
+
+        // block and macro bodies are also stored separately from the ast, for use with inheritance and macro calls
+        replace_undefined_in_nodes(&mut template.ast, &mut scope, vars, keep);
+        for block in template.blocks.values_mut() {
+            replace_undefined_in_nodes(&mut block.body, &mut scope, vars, keep);
+        }
+        for (_, block) in template.blocks_definitions.values_mut().flatten() {
+            replace_undefined_in_nodes(&mut block.body, &mut scope, vars, keep);
+        }
+        for def in template.macros.values_mut() {
+            let len = scope.len();
+            scope.extend(def.args.keys().map(|k| (k.clone(), true)));
+            replace_undefined_in_nodes(&mut def.body, &mut scope, vars, keep);
+            scope.truncate(len);
+        }
+    }
+
+    Ok(())
+}
+
+/// Names bound by the templates being rewritten (by for loops, macro definitions and set blocks), in order, each with
+/// whether it will have a value when rendered. Names bound by tags that are kept for a later pass have none.
+type Scope = Vec<(String, bool)>;
+
+/// Replace each variable block in `nodes` that references a variable that is neither defined in `vars` nor bound in
+/// `scope`.
+///
+/// If `keep` is true, if tags with a condition that uses such a variable, and for tags that loop over one, are also
+/// kept as source text, along with their end tags. The nodes inside them are still rewritten and rendered, so that
+/// every branch of a kept if tag is rendered, as is the body of a kept for loop once.
+/// Otherwise, such variables are read as empty strings in if conditions, and for loops over them run no times, leaving
+/// only their `else` bodies.
+fn replace_undefined_in_nodes(
+    nodes: &mut Vec<Node>,
+    scope: &mut Scope,
+    vars: &Map<String, Value>,
+    keep: bool,
+) {
+    for node in std::mem::take(nodes) {
+        match node {
+            Node::VariableBlock(ws, expr) if uses_undefined(&expr, scope, vars) => {
+                nodes.push(Node::Text(if keep {
+                    tag_source("{{", ws, &expr_source(&expr), "}}")
+                } else {
+                    String::new()
+                }));
+            }
+            Node::MacroDefinition(ws, mut def, end_ws) => {
+                let len = scope.len();
+                scope.extend(def.args.keys().map(|k| (k.clone(), true)));
+                replace_undefined_in_nodes(&mut def.body, scope, vars, keep);
+                scope.truncate(len);
+                nodes.push(Node::MacroDefinition(ws, def, end_ws));
+            }
+            Node::FilterSection(ws, mut sect, end_ws) => {
+                replace_undefined_in_nodes(&mut sect.body, scope, vars, keep);
+                nodes.push(Node::FilterSection(ws, sect, end_ws));
+            }
+            Node::Block(ws, mut block, end_ws) => {
+                replace_undefined_in_nodes(&mut block.body, scope, vars, keep);
+                nodes.push(Node::Block(ws, block, end_ws));
+            }
+            Node::Forloop(ws, mut stat, end_ws) if uses_undefined(&stat.container, scope, vars) => {
+                if keep {
+                    let key = stat
+                        .key
+                        .as_ref()
+                        .map(|k| format!("{k}, "))
+                        .unwrap_or_default();
+                    let tag = format!(
+                        "for {key}{} in {}",
+                        stat.value,
+                        expr_source(&stat.container)
+                    );
+                    nodes.push(Node::Text(tag_source("{%", ws, &tag, "%}")));
+
+                    // the names bound by the loop are left undefined, so that their uses are kept too
+                    let len = scope.len();
+                    scope.extend(stat.key.iter().map(|k| (k.clone(), false)));
+                    scope.push((stat.value.clone(), false));
+                    scope.push(("loop".to_string(), false));
+                    replace_undefined_in_nodes(&mut stat.body, scope, vars, keep);
+                    scope.truncate(len);
+                    nodes.append(&mut stat.body);
+
+                    if let Some(mut fallback) = stat.empty_body {
+                        nodes.push(Node::Text("{% else %}".to_string()));
+                        replace_undefined_in_nodes(&mut fallback, scope, vars, keep);
+                        nodes.append(&mut fallback);
+                    }
+                    nodes.push(Node::Text(tag_source("{%", end_ws, "endfor", "%}")));
+                } else if let Some(mut fallback) = stat.empty_body {
+                    replace_undefined_in_nodes(&mut fallback, scope, vars, keep);
+                    nodes.append(&mut fallback);
+                }
+            }
+            Node::Forloop(ws, mut stat, end_ws) => {
+                let len = scope.len();
+                scope.extend(stat.key.iter().map(|k| (k.clone(), true)));
+                scope.push((stat.value.clone(), true));
+                scope.push(("loop".to_string(), true));
+                replace_undefined_in_nodes(&mut stat.body, scope, vars, keep);
+                scope.truncate(len);
+
+                if let Some(fallback) = &mut stat.empty_body {
+                    replace_undefined_in_nodes(fallback, scope, vars, keep);
+                }
+                nodes.push(Node::Forloop(ws, stat, end_ws));
+            }
+            Node::If(stat, end_ws)
+                if keep
+                    && stat
+                        .conditions
+                        .iter()
+                        .any(|(_, cond, _)| uses_undefined(cond, scope, vars)) =>
+            {
+                for (i, (ws, cond, mut body)) in stat.conditions.into_iter().enumerate() {
+                    let tag = format!(
+                        "{} {}",
+                        if i == 0 { "if" } else { "elif" },
+                        expr_source(&cond)
+                    );
+                    nodes.push(Node::Text(tag_source("{%", ws, &tag, "%}")));
+                    replace_undefined_in_nodes(&mut body, scope, vars, keep);
+                    nodes.append(&mut body);
+                }
+                if let Some((ws, mut body)) = stat.otherwise {
+                    nodes.push(Node::Text(tag_source("{%", ws, "else", "%}")));
+                    replace_undefined_in_nodes(&mut body, scope, vars, keep);
+                    nodes.append(&mut body);
+                }
+                nodes.push(Node::Text(tag_source("{%", end_ws, "endif", "%}")));
+            }
+            Node::If(mut stat, end_ws) => {
+                for (_, cond, body) in &mut stat.conditions {
+                    replace_undefined_in_expr(cond, scope, vars);
+                    replace_undefined_in_nodes(body, scope, vars, keep);
+                }
+                if let Some((_, body)) = &mut stat.otherwise {
+                    replace_undefined_in_nodes(body, scope, vars, keep);
+                }
+                nodes.push(Node::If(stat, end_ws));
+            }
+            node => nodes.push(node),
+        }
+    }
+}
+
+/// Get the source text of a tag, e.g. `{%- if x %}` from `open` = `{%`, `inner` = `if x` and `close` = `%}`, including
+/// whitespace control markers from `ws`.
+fn tag_source(open: &str, ws: WS, inner: &str, close: &str) -> String {
+    format!(
+        "{open}{} {inner} {}{close}",
+        if ws.left { "-" } else { "" },
+        if ws.right { "-" } else { "" }
+    )
+}
 
-    Ok(get_vars
+/// Check whether `expr` uses a variable that is neither defined in `vars` nor bound in `scope`.
+fn uses_undefined(expr: &Expr, scope: &Scope, vars: &Map<String, Value>) -> bool {
+    get_expr_variable_idents(expr, true)
         .iter()
-        .filter_map(|k| {
-            // get the base variable name -- if it is an object, ignore accessors
-            let base = &k[..k.find('.').unwrap_or_else(|| k.len())];
-            let base = &base[..base.find('[').unwrap_or_else(|| base.len())];
+        .any(|id| is_ident_undefined(id, scope, vars))
+}
+
+/// Replace each variable identifier in `expr` that is neither defined in `vars` nor bound in `scope` with an empty
+/// string.
+fn replace_undefined_in_expr(expr: &mut Expr, scope: &Scope, vars: &Map<String, Value>) {
+    replace_undefined_in_expr_val(&mut expr.val, scope, vars);
+    for filter in &mut expr.filters {
+        for arg in filter.args.values_mut() {
+            replace_undefined_in_expr(arg, scope, vars);
+        }
+    }
+}
+
+/// See `replace_undefined_in_expr`.
+fn replace_undefined_in_expr_val(val: &mut ExprVal, scope: &Scope, vars: &Map<String, Value>) {
+    match val {
+        ExprVal::Ident(id)
+            if split_ident(id)
+                .iter()
+                .any(|i| i[0] != BUILTIN_VARIABLES_IDENT && is_ident_undefined(i, scope, vars)) =>
+        {
+            *val = ExprVal::String(String::new());
+        }
+        ExprVal::Math(MathExpr { lhs, rhs, .. })
+        | ExprVal::Logic(LogicExpr { lhs, rhs, .. })
+        | ExprVal::In(In { lhs, rhs, .. }) => {
+            replace_undefined_in_expr(lhs, scope, vars);
+            replace_undefined_in_expr(rhs, scope, vars);
+        }
+        ExprVal::Test(test) => {
+            for arg in &mut test.args {
+                replace_undefined_in_expr(arg, scope, vars);
+            }
+        }
+        ExprVal::MacroCall(MacroCall { args, .. })
+        | ExprVal::FunctionCall(FunctionCall { args, .. }) => {
+            for arg in args.values_mut() {
+                replace_undefined_in_expr(arg, scope, vars);
+            }
+        }
+        ExprVal::Array(arr) => {
+            for expr in arr {
+                replace_undefined_in_expr(expr, scope, vars);
+            }
+        }
+        ExprVal::StringConcat(concat) => {
+            for val in &mut concat.values {
+                replace_undefined_in_expr_val(val, scope, vars);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Check whether the variable identified by `ident` (split as returned by `get_expr_variable_idents`) is neither
+/// defined in `vars` nor bound in `scope`.
+fn is_ident_undefined(ident: &[&str], scope: &Scope, vars: &Map<String, Value>) -> bool {
+    match scope.iter().rev().find(|(name, _)| name == ident[0]) {
+        Some((_, bound)) => !bound,
+        None => !is_var_defined(ident, vars),
+    }
+}
+
+/// Check whether the variable identified by `ident` (split as returned by `get_expr_variable_idents`) is defined in
+/// `vars`, following accessed object fields and list indices.
+/// Accesses that can't be followed statically (e.g. into a variable used as a key) are assumed to be defined.
+fn is_var_defined(ident: &[&str], vars: &Map<String, Value>) -> bool {
+    let mut val = match vars.get(ident[0]) {
+        Some(v) => v,
+        None => return false,
+    };
+
+    for key in &ident[1..] {
+        val = match val {
+            Value::Object(o) => match o.get(*key) {
+                Some(v) => v,
+                None => return false,
+            },
+            Value::Array(a) => match key.parse::<usize>() {
+                Ok(i) => match a.get(i) {
+                    Some(v) => v,
+                    None => return false,
+                },
+                Err(_) => return true,
+            },
+            _ => return true,
+        };
+    }
+
+    true
+}
 
-            if set_vars.contains(&&base.to_string()) {
-                None
-            } else {
-                Some(k.to_string())
+/// Get source text equivalent to the expression `expr`, to be parsed again by a later render.
+fn expr_source(expr: &Expr) -> String {
+    let mut ret = expr_val_source(&expr.val);
+    for filter in &expr.filters {
+        ret = format!("{ret} | {}", function_call_source(filter, true));
+    }
+
+    if expr.negated {
+        format!("not {ret}")
+    } else {
+        ret
+    }
+}
+
+/// Get source text equivalent to the expression value `val`.
+fn expr_val_source(val: &ExprVal) -> String {
+    // operands of operators are parenthesised, as the original precedence isn't kept in the ast
+    let operand = |e: &Expr| match e.val {
+        ExprVal::Math(_) | ExprVal::Logic(_) | ExprVal::In(_) if e.filters.is_empty() => {
+            format!("({})", expr_source(e))
+        }
+        _ => expr_source(e),
+    };
+
+    match val {
+        ExprVal::String(s) => match ['"', '\'', '`'].iter().find(|q| !s.contains(**q)) {
+            Some(q) => format!("{q}{s}{q}"),
+            None => format!("\"{s}\""),
+        },
+        ExprVal::Int(i) => i.to_string(),
+        ExprVal::Float(f) => format!("{f:?}"),
+        ExprVal::Bool(b) => b.to_string(),
+        ExprVal::Ident(id) => id.clone(),
+        ExprVal::Math(m) => format!("{} {} {}", operand(&m.lhs), m.operator, operand(&m.rhs)),
+        ExprVal::Logic(l) => format!("{} {} {}", operand(&l.lhs), l.operator, operand(&l.rhs)),
+        ExprVal::Test(t) => format!(
+            "{} is {}{}{}",
+            t.ident,
+            if t.negated { "not " } else { "" },
+            t.name,
+            match t.args.is_empty() {
+                true => String::new(),
+                false => format!(
+                    "({})",
+                    t.args
+                        .iter()
+                        .map(expr_source)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
             }
-        })
-        .collect())
+        ),
+        ExprVal::MacroCall(call) => format!(
+            "{}::{}({})",
+            call.namespace,
+            call.name,
+            kwargs_source(&call.args)
+        ),
+        ExprVal::FunctionCall(call) => function_call_source(call, false),
+        ExprVal::Array(arr) => format!(
+            "[{}]",
+            arr.iter().map(expr_source).collect::<Vec<_>>().join(", ")
+        ),
+        ExprVal::StringConcat(concat) => concat
+            .values
+            .iter()
+            .map(expr_val_source)
+            .collect::<Vec<_>>()
+            .join(" ~ "),
+        ExprVal::In(i) => format!(
+            "{} {}in {}",
+            operand(&i.lhs),
+            if i.negated { "not " } else { "" },
+            operand(&i.rhs)
+        ),
+    }
+}
+
+/// Get source text equivalent to a function or filter call. Filters without arguments are written without brackets.
+fn function_call_source(call: &FunctionCall, is_filter: bool) -> String {
+    if is_filter && call.args.is_empty() {
+        call.name.clone()
+    } else {
+        format!("{}({})", call.name, kwargs_source(&call.args))
+    }
+}
+
+/// Get source text for a set of keyword arguments, in a consistent order.
+fn kwargs_source(args: &HashMap<String, Expr>) -> String {
+    let mut args = args
+        .iter()
+        .map(|(k, v)| format!("{k}={}", expr_source(v)))
+        .collect::<Vec<_>>();
+    args.sort();
+    args.join(", ")
 }
 
 /// Iterate through each top-level node inside a template's abstract syntax tree structure.
@@ -105,7 +601,7 @@ fn find_all_matches(
 ) -> DevinitResult<Vec<&Node>> {
     let mut ret = vec![];
     for node in &template.ast {
-        ret.append(&mut find_all_matches_node(&node, predicate)?);
+        ret.append(&mut find_all_matches_node(node, predicate)?);
     }
     Ok(ret)
 }
@@ -181,8 +677,11 @@ fn get_templates_recursive<S: AsRef<str>>(
         .ok_or(DevinitError::IdNotFoundError(tpl_name.as_ref().to_string()))?;
 
     // get any directives that import other templates (i.e. include, extends, ...)
-    let directives = find_all_matches(&template, |n| {
-        matches!(n, Node::Include(_, _, _) | Node::Extends(_, _))
+    let directives = find_all_matches(template, |n| {
+        matches!(
+            n,
+            Node::Include(_, _, _) | Node::Extends(_, _) | Node::ImportMacro(_, _, _)
+        )
     })?;
 
     // build vector of templates
@@ -191,55 +690,368 @@ fn get_templates_recursive<S: AsRef<str>>(
         if let Node::Include(_, ids, _) = dir {
             // `ids` is the list of template ids to check when including
             for id in ids {
-                ret.append(&mut get_templates_recursive(&tera, id)?)
+                ret.append(&mut get_templates_recursive(tera, id)?)
             }
-        } else if let Node::Extends(_, id) = dir {
-            ret.append(&mut get_templates_recursive(&tera, id)?)
+        } else if let Node::Extends(_, id) | Node::ImportMacro(_, id, _) = dir {
+            ret.append(&mut get_templates_recursive(tera, id)?)
         }
     }
     ret.push(template);
 
-    return Ok(ret);
+    Ok(ret)
 }
 
-/// Recursively get all variable identifiers necessary to evaluate the specified expression, including any referenced in function
-/// invocations.
+/// Recursively get all variable identifiers necessary to evaluate the specified expression, including any referenced in
+/// operands, tests, filter arguments and function or macro invocations.
 ///
 /// A list of lists is returned - each inner list pertains to one variable, and is split by dot and bracket delimiters. Therefore, the
 /// first item in each list is the actual variable name, and any other items are accessed properties, if the variable is an object.
-fn get_expr_variable_idents<'a>(expr: &'a Expr, ignore_builtins: bool) -> Vec<Vec<&'a str>> {
+fn get_expr_variable_idents(expr: &Expr, ignore_builtins: bool) -> Vec<Vec<&str>> {
+    let mut ret = get_expr_val_variable_idents(&expr.val);
+    for filter in &expr.filters {
+        for arg in filter.args.values() {
+            ret.append(&mut get_expr_variable_idents(arg, false));
+        }
+    }
+
+    if ignore_builtins {
+        ret.retain(|s| s[0] != BUILTIN_VARIABLES_IDENT);
+    }
+
+    ret
+}
+
+/// Recursively get all variable identifiers necessary to evaluate the specified expression value.
+/// See `get_expr_variable_idents`.
+fn get_expr_val_variable_idents(val: &ExprVal) -> Vec<Vec<&str>> {
     let mut ret = vec![];
 
-    match &expr.val {
-        ExprVal::Ident(id) => ret.push(
-            id.split(&['.', '['])
-                .map(|s| &s[..s.find("]").unwrap_or_else(|| s.len())])
-                .collect(),
-        ),
+    match val {
+        ExprVal::Ident(id) => ret.append(&mut split_ident(id)),
+        ExprVal::Math(MathExpr { lhs, rhs, .. })
+        | ExprVal::Logic(LogicExpr { lhs, rhs, .. })
+        | ExprVal::In(In { lhs, rhs, .. }) => {
+            ret.append(&mut get_expr_variable_idents(lhs, false));
+            ret.append(&mut get_expr_variable_idents(rhs, false));
+        }
+        ExprVal::Test(test) => {
+            // testing whether a variable is defined doesn't need it to be
+            if test.name != "defined" && test.name != "undefined" {
+                ret.append(&mut split_ident(&test.ident));
+            }
+            for arg in &test.args {
+                ret.append(&mut get_expr_variable_idents(arg, false));
+            }
+        }
         ExprVal::MacroCall(call) => {
-            for arg in &call.args {
-                ret.append(&mut get_expr_variable_idents(&arg.1, ignore_builtins))
+            for arg in call.args.values() {
+                ret.append(&mut get_expr_variable_idents(arg, false));
             }
         }
         ExprVal::FunctionCall(call) => {
-            for arg in &call.args {
-                ret.append(&mut get_expr_variable_idents(&arg.1, ignore_builtins))
+            for arg in call.args.values() {
+                ret.append(&mut get_expr_variable_idents(arg, false));
             }
         }
         ExprVal::Array(arr) => {
             for expr in arr {
-                ret.append(&mut get_expr_variable_idents(&expr, ignore_builtins))
+                ret.append(&mut get_expr_variable_idents(expr, false));
             }
         }
-        _ => {}
+        ExprVal::StringConcat(concat) => {
+            for val in &concat.values {
+                ret.append(&mut get_expr_val_variable_idents(val));
+            }
+        }
+        ExprVal::String(_) | ExprVal::Int(_) | ExprVal::Float(_) | ExprVal::Bool(_) => {}
     }
 
-    if ignore_builtins {
-        ret = ret
-            .into_iter()
-            .filter(|s| s[0] != BUILTIN_VARIABLES_IDENT)
-            .collect();
+    ret
+}
+
+/// Split a variable identifier into its name and any accessed properties, e.g. `a.b["c"][0]` into `[a, b, c, 0]`.
+/// Variables used as keys in square brackets (e.g. `b` in `a[b]`) are returned as separate identifiers, and no
+/// properties are known after them.
+fn split_ident(id: &str) -> Vec<Vec<&str>> {
+    let mut ret = vec![];
+    let mut path = vec![];
+    let mut dynamic = false;
+    let mut rest = id;
+
+    loop {
+        // properties are separated by dots up until the next square bracket
+        let end = rest.find('[').unwrap_or(rest.len());
+        if !dynamic {
+            path.extend(rest[..end].split('.').filter(|s| !s.is_empty()));
+        }
+        if end == rest.len() {
+            break;
+        }
+
+        rest = &rest[end + 1..];
+        let close = rest.find(']').unwrap_or(rest.len());
+        let key = rest[..close].trim();
+        if key.starts_with(['"', '\'', '`']) || key.parse::<usize>().is_ok() {
+            if !dynamic {
+                path.push(key.trim_matches(['"', '\'', '`']));
+            }
+        } else {
+            ret.append(&mut split_ident(key));
+            dynamic = true;
+        }
+        rest = &rest[(close + 1).min(rest.len())..];
     }
 
+    ret.insert(0, path);
     ret
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::templater::Context;
+
+    fn context(templates: &[(&str, &str)]) -> ContextArcMutex {
+        let mut context = Context::new();
+        context
+            .tera_mut()
+            .add_raw_templates(templates.iter().copied())
+            .unwrap();
+        Arc::new(Mutex::new(context))
+    }
+
+    fn missing(templates: &[(&str, &str)]) -> Vec<String> {
+        get_missing_template_vars(context(templates), templates[0].0).unwrap()
+    }
+
+    /// Parse `source` as the expression of a variable block.
+    fn parse_expr(source: &str) -> Expr {
+        let template = Template::new("expr", None, &format!("{{{{ {source} }}}}")).unwrap();
+        match template.ast.into_iter().next() {
+            Some(Node::VariableBlock(_, expr)) => expr,
+            node => panic!("expected a variable block, got {:?}", node),
+        }
+    }
+
+    #[test]
+    fn split_ident_paths() {
+        assert_eq!(split_ident("a"), vec![vec!["a"]]);
+        assert_eq!(split_ident("a.b.c"), vec![vec!["a", "b", "c"]]);
+        assert_eq!(
+            split_ident("a.b[\"c\"][0].d"),
+            vec![vec!["a", "b", "c", "0", "d"]]
+        );
+        assert_eq!(split_ident("a['c']"), vec![vec!["a", "c"]]);
+    }
+
+    #[test]
+    fn split_ident_dynamic_keys() {
+        assert_eq!(split_ident("a[b].c"), vec![vec!["a"], vec!["b"]]);
+        assert_eq!(
+            split_ident("a.x[b.y[c]]"),
+            vec![vec!["a", "x"], vec!["b", "y"], vec!["c"]]
+        );
+    }
+
+    #[test]
+    fn expr_source_round_trip() {
+        for source in [
+            "name",
+            "author.name | upper",
+            "x + 1",
+            "a and not b",
+            "name is matching(\"^[a-z]+$\")",
+            "items | join(sep=\", \")",
+            "now(utc=true) | date(format=\"%Y\")",
+            "\"a\" ~ b",
+            "x in [1, 2]",
+        ] {
+            assert_eq!(expr_source(&parse_expr(source)), source);
+        }
+    }
+
+    #[test]
+    fn missing_vars_are_sorted_and_builtins_ignored() {
+        assert_eq!(
+            missing(&[(
+                "t",
+                "{{ b }}{{ a.x }}{{ BUILTIN.file_name }}{{ b | upper }}"
+            )]),
+            ["a", "b"]
+        );
+    }
+
+    #[test]
+    fn for_loop_scoping() {
+        assert_eq!(
+            missing(&[(
+                "t",
+                "{% for k, v in items %}{{ k }}{{ v }}{{ loop.index }}{{ other }}{% else %}{{ v }}{% endfor %}{{ k }}"
+            )]),
+            ["items", "k", "other", "v"]
+        );
+    }
+
+    #[test]
+    fn set_scoping() {
+        // set binds the name from then on, and only within the enclosing loop
+        assert_eq!(
+            missing(&[(
+                "t",
+                "{{ a }}{% set a = 1 %}{{ a }}{% for i in xs %}{% set b = i %}{{ b }}{% endfor %}{{ b }}"
+            )]),
+            ["a", "b", "xs"]
+        );
+    }
+
+    #[test]
+    fn set_global_scoping() {
+        assert_eq!(
+            missing(&[(
+                "t",
+                "{% for i in xs %}{% set_global b = i %}{% endfor %}{{ b }}{% set c = b %}{{ c }}"
+            )]),
+            ["xs"]
+        );
+    }
+
+    #[test]
+    fn macro_scoping() {
+        assert_eq!(
+            missing(&[(
+                "t",
+                "{% macro m(a, b=1) %}{{ a }}{{ b }}{{ c }}{% endmacro m %}{{ self::m(a=d, b=e) }}{{ a }}"
+            )]),
+            ["a", "c", "d", "e"]
+        );
+    }
+
+    #[test]
+    fn imported_macros() {
+        assert_eq!(
+            missing(&[
+                ("t", "{% import \"macros\" as m %}{{ m::greet(name=who) }}"),
+                (
+                    "macros",
+                    "{% macro greet(name) %}{{ greeting }} {{ name }}{% endmacro greet %}"
+                ),
+            ]),
+            ["greeting", "who"]
+        );
+    }
+
+    #[test]
+    fn is_defined_tests() {
+        assert_eq!(
+            missing(&[(
+                "t",
+                "{% if a is defined and b is not undefined %}{{ a }}{% endif %}{% if c is matching(\"x\") %}{% endif %}"
+            )]),
+            ["a", "c"]
+        );
+    }
+
+    #[test]
+    fn includes_and_inheritance() {
+        assert_eq!(
+            missing(&[
+                (
+                    "child",
+                    "{% extends \"base\" %}{% block body %}{% set x = 1 %}{% include \"inc\" %}{{ super() }}{% endblock body %}"
+                ),
+                ("base", "{{ title }}{% block body %}{{ base_body }}{% endblock body %}"),
+                ("inc", "{{ x }}{{ y }}"),
+            ]),
+            ["base_body", "title", "y"]
+        );
+    }
+
+    fn replaced(source: &str, vars: &[(&str, Value)], keep: bool) -> String {
+        let context = context(&[("t", source)]);
+        let vars = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect::<Map<_, _>>();
+        replace_undefined_vars(context.clone(), "t", &vars, keep).unwrap();
+
+        let render = context
+            .lock()
+            .unwrap()
+            .tera()
+            .render("t", &tera::Context::from_serialize(&vars).unwrap())
+            .unwrap();
+        render
+    }
+
+    #[test]
+    fn replace_undefined_keep() {
+        assert_eq!(
+            replaced(
+                "{{ a }} {{ b | upper }} {{- c.d -}} {% for i in [1] %}{{ i }}{% endfor %}",
+                &[("a", Value::from("A")), ("c", Value::from(Map::new()))],
+                true
+            ),
+            "A {{ b | upper }}{{- c.d -}}1"
+        );
+    }
+
+    #[test]
+    fn replace_undefined_empty() {
+        assert_eq!(
+            replaced(
+                "{{ a }}[{{ b }}]{% set s = 1 %}{{ s }}",
+                &[("a", Value::from("A"))],
+                false
+            ),
+            "A[]1"
+        );
+    }
+
+    #[test]
+    fn replace_undefined_empty_conditions_and_loops() {
+        let source = "{% if flag == \"y\" %}Y{% elif author.email %}E{% else %}N{% endif %}\
+            {% for x in xs %}{{ x }}{% else %}none{% endfor %}\
+            {% for k, v in map %}{{ k }}{% endfor %}";
+        assert_eq!(
+            replaced(source, &[("author", Value::from(Map::new()))], false),
+            "Nnone"
+        );
+        assert_eq!(
+            replaced(
+                source,
+                &[("flag", Value::from("y")), ("xs", Value::from(vec![1]))],
+                false
+            ),
+            "Y1"
+        );
+    }
+
+    #[test]
+    fn replace_undefined_keep_if_tags() {
+        assert_eq!(
+            replaced(
+                "{% if a %}A{% endif %}\
+                {%- if flag == \"y\" -%} {{ a }} {%- elif a %}{{ b }}{% else %}N{% endif %}",
+                &[("a", Value::from("A"))],
+                true
+            ),
+            "A{%- if flag == \"y\" -%}A{%- elif a %}{{ b }}{% else %}N{% endif %}"
+        );
+    }
+
+    #[test]
+    fn replace_undefined_keep_for_tags() {
+        assert_eq!(
+            replaced(
+                "{% for k, v in map %}{{ k }}={{ v.x }}{{ a }}{{ loop.index }}{% endfor %}\
+                {% for x in xs -%} {% for i in [1] %}{{ i }}{{ x }}{% endfor %}{% else %}none{% endfor %}",
+                &[("a", Value::from("A")), ("x", Value::from("X"))],
+                true
+            ),
+            "{% for k, v in map %}{{ k }}={{ v.x }}A{{ loop.index }}{% endfor %}\
+            {% for x in xs -%}1{{ x }}{% else %}none{% endfor %}"
+        );
+    }
+}
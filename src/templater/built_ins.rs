@@ -5,10 +5,26 @@
  *   See the LICENCE file for more information.
  */
 
+use std::{
+    env,
+    ffi::OsStr,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use chrono::{Datelike, Local, SecondsFormat};
 use serde::Serialize;
 
+use crate::{
+    error::{DevinitError, DevinitResult},
+    files::GitUser,
+};
+
+use super::language_specifics::{comment_style_from_lang, lang_id_from_filename, CommentStyle};
+
 /// The identifier that must be used inside template source files to access built-in variables.
-pub static BUILTIN_VARIABLES_IDENT: &'static str = "BUILTIN";
+pub static BUILTIN_VARIABLES_IDENT: &str = "BUILTIN";
 
 /// A struct containing members for built-in template variables.
 /// This data can be accessed within templates via an object identified by the string
@@ -26,4 +42,407 @@ pub struct BuiltinVariables {
     /// Name of the parent directory to the output file, if rendering a file into a fs path.
     /// Empty if using --dry-run.
     pub parent_name: String,
+
+    /// Output path relative to the project directory, if rendering a file in a project.
+    pub path: String,
+
+    /// Name of the project directory, if rendering a file in a project.
+    /// Empty if using --dry-run.
+    pub project_name: String,
+
+    /// Absolute path of the output file, if rendering a file into a fs path.
+    /// Empty if using --dry-run.
+    pub abs_path: String,
+
+    /// The current local date and time.
+    pub now: DateTimeBuiltins,
+
+    /// Name of the current OS user.
+    pub user: String,
+
+    /// Hostname of the current machine.
+    pub hostname: String,
+
+    /// The git user identity, from the global git config and the config of the repository being rendered into.
+    pub git: GitUser,
+
+    /// Id of the language of the output file, as detected from its filename.
+    /// Empty if the language is unknown or there is no output filename.
+    pub lang: String,
+
+    /// Comment style of the language of the output file.
+    /// Each field is empty if the language is unknown or has no comment style.
+    pub comment: CommentStyle,
+
+    /// Absolute path of the project root enclosing the output file, found from marker files such as Cargo.toml.
+    /// Empty if there is no enclosing project root.
+    pub root_dir: String,
+
+    /// Output path relative to the project root, with `/` separators.
+    /// Empty if there is no enclosing project root.
+    pub root_path: String,
+
+    /// Rust module path of the output file, e.g. `net::http::client`.
+    /// Empty unless `lang` is rust.
+    pub module_path: String,
+
+    /// Package of the output file, e.g. `com.acme`.
+    /// Empty unless `lang` is java, kotlin or scala.
+    pub package: String,
+
+    /// C/C++ include guard for the output file, e.g. `NET_HTTP_CLIENT_H`.
+    /// Empty unless `lang` is c or cpp.
+    pub include_guard: String,
+}
+
+/// Files or directories, any of which marks the directory containing it as a project root.
+const PROJECT_ROOT_MARKERS: [&str; 8] = [
+    "Cargo.toml",
+    "package.json",
+    "pom.xml",
+    "build.gradle",
+    "build.gradle.kts",
+    "go.mod",
+    "pyproject.toml",
+    ".git",
+];
+
+/// Source directories (relative to the project root) that do not form part of a Java-like package name.
+const JVM_SOURCE_DIRS: [&str; 7] = [
+    "src/main/java",
+    "src/test/java",
+    "src/main/kotlin",
+    "src/test/kotlin",
+    "src/main/scala",
+    "src/test/scala",
+    "src",
+];
+
+/// The current date and time in several forms, accessed in templates via `BUILTIN.now`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DateTimeBuiltins {
+    /// Date in the form YYYY-MM-DD
+    pub date: String,
+
+    /// Time in the form HH:MM:SS
+    pub time: String,
+
+    /// Date and time in RFC 3339 format, e.g. 2024-05-01T13:45:00+01:00
+    pub iso: String,
+
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+
+    /// Seconds since the Unix epoch
+    pub timestamp: i64,
+}
+
+impl BuiltinVariables {
+    /// Get the built-in variables for rendering a file into the path `path`: its filename, file contents (if the path
+    /// exists - otherwise empty str), etc.
+    pub fn for_output_path<P: AsRef<Path>>(path: P) -> DevinitResult<Self> {
+        let mut ret = Self {
+            // path file name
+            file_name: path
+                .as_ref()
+                .file_name()
+                .and_then(OsStr::to_str)
+                .ok_or(DevinitError::FileReadWriteError(format!(
+                    "Output file path {:?} is not valid UTF-8",
+                    path.as_ref()
+                )))?
+                .to_owned(),
+            // path parent directory name
+            parent_name: path
+                .as_ref()
+                .parent()
+                .unwrap_or(PathBuf::from("").as_path())
+                .file_name()
+                .and_then(OsStr::to_str)
+                .ok_or(DevinitError::FileReadWriteError(format!(
+                    "Path of parent directory of output file {:?} is not valid UTF-8",
+                    path.as_ref()
+                )))?
+                .to_string(),
+            abs_path: path.as_ref().display().to_string(),
+            // file contents
+            file_contents: match fs::read_to_string(&path) {
+                Ok(c) => c,
+                Err(e) => {
+                    if e.kind() == ErrorKind::NotFound {
+                        String::from("")
+                    } else {
+                        return Err(DevinitError::FileReadWriteError(format!(
+                            "When attempting to read to {BUILTIN_VARIABLES_IDENT}.file_contents: {e}",
+                        )));
+                    }
+                }
+            },
+            ..Default::default()
+        };
+        ret.set_file_name(ret.file_name.clone());
+
+        if let Some(root) = find_project_root(&path) {
+            let rel_path = path.as_ref().strip_prefix(&root).unwrap_or(path.as_ref());
+            ret.set_root(Some(root.as_path()), rel_path);
+        }
+
+        Ok(ret)
+    }
+
+    /// Set the output filename, along with the language detected from it and its comment style.
+    pub fn set_file_name<S: AsRef<str>>(&mut self, file_name: S) {
+        self.file_name = file_name.as_ref().to_string();
+
+        let lang = lang_id_from_filename(&self.file_name);
+        self.lang = lang.unwrap_or_default().to_string();
+        self.comment = lang
+            .and_then(comment_style_from_lang)
+            .copied()
+            .unwrap_or_default();
+    }
+
+    /// Set the project root directory and the output path relative to it, along with the names derived from that
+    /// path for the language in `lang`. The root directory is left empty if `root` is `None`.
+    ///
+    /// The filename should be set with `set_file_name` before calling this.
+    pub fn set_root<P: AsRef<Path>>(&mut self, root: Option<P>, rel_path: P) {
+        self.root_dir = root
+            .map(|r| r.as_ref().display().to_string())
+            .unwrap_or_default();
+        self.root_path = rel_path
+            .as_ref()
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        self.module_path = String::new();
+        self.package = String::new();
+        self.include_guard = String::new();
+
+        match self.lang.as_str() {
+            "rust" => {
+                let parts: Vec<&str> = self.root_path.split('/').collect();
+
+                // find the directory of the crate that the file is in, and whether the file is that crate's root
+                // binaries, examples, tests and benchmarks are each a crate of their own
+                let (crate_dir, is_crate_root) = match parts.as_slice() {
+                    ["build.rs"] => (1, true),
+                    ["src", "bin", _] | ["examples" | "tests" | "benches", _] => {
+                        (parts.len(), true)
+                    }
+                    ["src", "bin", _, ..] => (3, false),
+                    ["examples" | "tests" | "benches", _, ..] => (2, false),
+                    ["src", _, ..] => (1, false),
+                    _ => (0, false),
+                };
+
+                let mut parts = parts[crate_dir..].to_vec();
+                if let Some(last) = parts.pop() {
+                    let stem = last.strip_suffix(".rs").unwrap_or(last);
+                    // mod.rs is named by its directory, and lib.rs and main.rs are the crate root
+                    if stem != "mod" && !(parts.is_empty() && (stem == "lib" || stem == "main")) {
+                        parts.push(stem);
+                    }
+                }
+                if !is_crate_root {
+                    self.module_path = parts.join("::");
+                }
+            }
+            "java" | "kotlin" | "scala" => {
+                let dir = self
+                    .root_path
+                    .rsplit_once('/')
+                    .map(|(d, _)| d)
+                    .unwrap_or("");
+                let pkg_dir = JVM_SOURCE_DIRS
+                    .iter()
+                    .find_map(|src| {
+                        dir.strip_prefix(src)
+                            .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+                    })
+                    .unwrap_or(dir);
+                self.package = pkg_dir.trim_start_matches('/').replace('/', ".");
+            }
+            "c" | "cpp" => {
+                let path = ["include/", "src/"]
+                    .iter()
+                    .find_map(|src| self.root_path.strip_prefix(src))
+                    .unwrap_or(&self.root_path);
+                self.include_guard = path
+                    .chars()
+                    .map(|c| match c.is_ascii_alphanumeric() {
+                        true => c.to_ascii_uppercase(),
+                        false => '_',
+                    })
+                    .collect();
+            }
+            _ => (),
+        }
+    }
+
+    /// Set the variables describing the environment devinit is running in: the date and time, user, hostname and git
+    /// identity. `dir` is the directory being rendered into, which is used to find the git repository config.
+    pub fn load_environment<P: AsRef<Path>>(&mut self, dir: P) {
+        let now = Local::now();
+        self.now = DateTimeBuiltins {
+            date: now.format("%Y-%m-%d").to_string(),
+            time: now.format("%H:%M:%S").to_string(),
+            iso: now.to_rfc3339_opts(SecondsFormat::Secs, false),
+            year: now.year(),
+            month: now.month(),
+            day: now.day(),
+            timestamp: now.timestamp(),
+        };
+
+        self.user = env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_default();
+
+        self.hostname = hostname().unwrap_or_default();
+
+        self.git = GitUser::find(dir);
+    }
+}
+
+/// Look up the hostname of the current machine.
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: the buffer is valid for writes of its whole length, which is passed as the maximum to write
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return None;
+    }
+
+    // the name is null-terminated, unless it was truncated to fit the buffer
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8(buf[..len].to_vec()).ok()
+}
+
+/// Look up the hostname of the current machine, which Windows always sets in the environment.
+#[cfg(not(unix))]
+fn hostname() -> Option<String> {
+    env::var("COMPUTERNAME").ok()
+}
+
+/// Find the closest directory enclosing the output path `path` that contains a project root marker.
+pub fn find_project_root<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
+    find_project_root_with(path, |_| false)
+}
+
+/// Find the closest directory enclosing the output path `path` that contains a project root marker, counting the paths
+/// for which `is_output` is true as well as existing files (e.g. markers that are about to be written).
+pub fn find_project_root_with<P: AsRef<Path>>(
+    path: P,
+    is_output: impl Fn(&Path) -> bool,
+) -> Option<PathBuf> {
+    path.as_ref()
+        .ancestors()
+        .skip(1)
+        .find(|dir| {
+            PROJECT_ROOT_MARKERS.iter().any(|m| {
+                let marker = dir.join(m);
+                is_output(&marker) || marker.exists()
+            })
+        })
+        .map(Path::to_path_buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builtins for an output file at `rel_path` in a project rooted at `/proj`.
+    fn rooted(rel_path: &str) -> BuiltinVariables {
+        let mut builtins = BuiltinVariables::default();
+        builtins.set_file_name(rel_path.rsplit('/').next().unwrap());
+        builtins.set_root(Some(Path::new("/proj")), Path::new(rel_path));
+        builtins
+    }
+
+    #[test]
+    fn root_path() {
+        let builtins = rooted("src/net/http/client.rs");
+        assert_eq!(builtins.root_dir, Path::new("/proj").display().to_string());
+        assert_eq!(builtins.root_path, "src/net/http/client.rs");
+    }
+
+    #[test]
+    fn rust_module_path() {
+        assert_eq!(
+            rooted("src/net/http/client.rs").module_path,
+            "net::http::client"
+        );
+        assert_eq!(rooted("src/net/http/mod.rs").module_path, "net::http");
+        assert_eq!(rooted("src/lib.rs").module_path, "");
+        assert_eq!(rooted("src/main.rs").module_path, "");
+        assert_eq!(rooted("net/http.rs").module_path, "net::http");
+    }
+
+    #[test]
+    fn rust_crate_roots() {
+        for path in [
+            "build.rs",
+            "src/bin/tool.rs",
+            "src/bin/tool/main.rs",
+            "examples/demo.rs",
+            "examples/demo/main.rs",
+            "tests/cli.rs",
+            "tests/cli/main.rs",
+            "benches/parse.rs",
+        ] {
+            assert_eq!(rooted(path).module_path, "", "{}", path);
+        }
+
+        // other files beside a crate root are modules of that crate
+        assert_eq!(rooted("src/bin/tool/args.rs").module_path, "args");
+        assert_eq!(rooted("src/bin/tool/cmd/mod.rs").module_path, "cmd");
+        assert_eq!(rooted("examples/demo/util.rs").module_path, "util");
+    }
+
+    #[test]
+    fn java_package() {
+        let builtins = rooted("src/main/java/com/acme/Foo.java");
+        assert_eq!(builtins.lang, "java");
+        assert_eq!(builtins.package, "com.acme");
+        assert_eq!(builtins.module_path, "");
+        assert_eq!(builtins.include_guard, "");
+
+        assert_eq!(
+            rooted("src/test/java/com/acme/FooTest.java").package,
+            "com.acme"
+        );
+        assert_eq!(rooted("src/main/java/Foo.java").package, "");
+        assert_eq!(rooted("com/acme/Foo.java").package, "com.acme");
+    }
+
+    #[test]
+    fn c_include_guard() {
+        let builtins = rooted("include/net/http/client.h");
+        assert_eq!(builtins.include_guard, "NET_HTTP_CLIENT_H");
+        assert_eq!(builtins.package, "");
+
+        assert_eq!(rooted("src/util.hpp").include_guard, "UTIL_HPP");
+        assert_eq!(rooted("lib/my-util.h").include_guard, "LIB_MY_UTIL_H");
+    }
+
+    #[test]
+    fn other_languages_have_no_derived_names() {
+        let builtins = rooted("src/net/http/client.py");
+        assert_eq!(builtins.module_path, "");
+        assert_eq!(builtins.package, "");
+        assert_eq!(builtins.include_guard, "");
+    }
+
+    #[test]
+    fn derived_names_are_reset() {
+        let mut builtins = rooted("src/net/http/client.rs");
+        builtins.set_file_name("client.h");
+        builtins.set_root(None, Path::new("include/client.h"));
+        assert_eq!(builtins.root_dir, "");
+        assert_eq!(builtins.module_path, "");
+        assert_eq!(builtins.include_guard, "CLIENT_H");
+    }
 }
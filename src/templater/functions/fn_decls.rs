@@ -47,9 +47,10 @@ function! {
 }
 
 function! {
-    /// Get the comment style associated with the specified language id.
+    /// Get the comment style associated with the specified language id, in the order `[start, prefix, end]`.
     pub fn comment_by_lang(lang_id: String,) {
-        to_value(language_specifics::comment_style_from_lang(&lang_id)).unwrap_or("unknown".into())
+        let style = language_specifics::comment_style_from_lang(&lang_id).map(|c| [c.start, c.prefix, c.end]);
+        to_value(style).unwrap_or("unknown".into())
     }
 }
 
@@ -60,6 +61,17 @@ filter! {
     }
 }
 
+filter! {
+    /// Quote a given value so that it is passed as a single argument to `sh`, whatever characters it contains.
+    pub fn shell_quote(val: Value,) {
+        let text = match val {
+            Value::String(s) => s,
+            v => v.to_string(),
+        };
+        format!("'{}'", text.replace('\'', r"'\''"))
+    }
+}
+
 fn strip_trailing_newline(input: &str) -> &str {
-    input.trim_end_matches(&['\r', '\n'])
+    input.trim_end_matches(['\r', '\n'])
 }
@@ -37,6 +37,7 @@ macro_rules! filter {
     ) => {
         $(#[$($attrss)*])*
         pub fn $fname() -> impl Filter {
+            #[allow(unused_variables)]
             Box::new(|val: &Value, argv: &HashMap<String, Value>| -> Result<Value> {
                 let $base = from_value::<$basetyp>(val.clone()).map_err(|e| format!("{}", e))?;
                 $(
@@ -91,10 +91,9 @@ pub fn lang_id_from_filename<S: AsRef<str>>(filename: S) -> Option<&'static str>
     let filename = filename.as_ref();
     let ext = Path::new(&filename).extension().and_then(OsStr::to_str);
 
-    if ext.is_none() {
-        return from_standard_filename(&filename);
-    } else {
-        return from_ext(&ext.unwrap());
+    match ext {
+        Some(ext) => from_ext(ext),
+        None => from_standard_filename(filename),
     }
 }
 
@@ -112,16 +111,31 @@ fn from_standard_filename<S: AsRef<str>>(filename: S) -> Option<&'static str> {
         .map(|x| x.1)
 }
 
-/// A struct containing comment style information.
-/// Items are in order `CommentStyle(block_start, block_prefix, block_end)`
-/// E.g. `("/*", " *", "*/")` for C/C++,
-/// and `("", "#", "")` for Python.
-#[derive(Serialize)]
-pub struct CommentStyle(&'static str, &'static str, &'static str);
+/// A struct containing comment style information, preferring comment blocks.
+/// E.g. `{ start: "/*", prefix: " *", end: " */" }` for C/C++,
+/// and `{ start: "", prefix: "#", end: "" }` for Python.
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub struct CommentStyle {
+    /// Line that starts a comment block
+    pub start: &'static str,
+
+    /// Prefix of each line of commented text
+    pub prefix: &'static str,
+
+    /// Line that ends a comment block
+    pub end: &'static str,
+}
 
 macro_rules! style {
     ($id:literal, ($start:literal, $prefix:literal, $end:literal)) => {
-        ($id, CommentStyle($start, $prefix, $end))
+        (
+            $id,
+            CommentStyle {
+                start: $start,
+                prefix: $prefix,
+                end: $end,
+            },
+        )
     };
 }
 
@@ -11,7 +11,7 @@ use tera::Tera;
 pub mod fn_utils;
 pub mod fn_decls;
 
-mod language_specifics;
+pub mod language_specifics;
 
 /// Register all functions and filters onto the given Tera context
 pub fn register_functions(tera: &mut Tera) {
@@ -21,4 +21,5 @@ pub fn register_functions(tera: &mut Tera) {
     tera.register_function("comment_by_lang", fn_decls::comment_by_lang());
 
     tera.register_filter("wrap", fn_decls::wrap());
+    tera.register_filter("shell_quote", fn_decls::shell_quote());
 }
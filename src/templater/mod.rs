@@ -22,3 +22,6 @@ pub use renderer::*;
 
 mod tpl_objects;
 pub use tpl_objects::*;
+
+mod validate;
+pub use validate::*;
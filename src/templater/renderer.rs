@@ -6,14 +6,23 @@
  */
 
 use core::fmt;
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 
 use super::{
-    BuiltinVariables, ContextArcMutex, FileTemplate, ProjectTemplate, Template,
-    BUILTIN_VARIABLES_IDENT,
+    built_ins::find_project_root_with, BuiltinVariables, ContextArcMutex, FileTemplate,
+    PreHookKind, ProjectFileSource, ProjectTemplate, Template, BUILTIN_VARIABLES_IDENT,
+};
+use crate::{
+    error::{DevinitError, DevinitResult},
+    files::VariableDecl,
 };
-use crate::error::{DevinitError, DevinitResult};
 use miette::IntoDiagnostic;
+use serde::Serialize;
 use tera::Context;
 
 pub enum RendererVariant<'a> {
@@ -21,14 +30,45 @@ pub enum RendererVariant<'a> {
     Project(ProjectRenderer<'a>),
 }
 
+impl<'a> RendererVariant<'a> {
+    /// Get the variable declarations of the template being rendered
+    pub fn variable_decls(&self) -> &Vec<VariableDecl> {
+        match self {
+            RendererVariant::File(f) => &f.template().meta().variables,
+            RendererVariant::Project(p) => &p.template().meta().variables,
+        }
+    }
+
+    /// Get the context that the templates rendered by this renderer are stored in
+    pub fn context(&self) -> ContextArcMutex {
+        match self {
+            RendererVariant::File(f) => f.ctx_ref.clone(),
+            RendererVariant::Project(p) => p.ctx_ref.clone(),
+        }
+    }
+
+    /// Give this renderer its own copy of the context that its templates are stored in, so that the templates can be
+    /// modified for this render alone, without affecting other templates that share the context.
+    /// Returns the new context.
+    pub fn detach_context(&mut self) -> ContextArcMutex {
+        let ctx = Arc::new(Mutex::new(self.context().lock().unwrap().clone()));
+        match self {
+            RendererVariant::File(f) => f.ctx_ref = ctx.clone(),
+            RendererVariant::Project(p) => p.ctx_ref = ctx.clone(),
+        }
+        ctx
+    }
+}
+
 /// A trait defining behaviour to render a template (i.e. either produce evaluated string output, or a project folder structure)
 pub trait Renderer<'a>: fmt::Debug + Clone {
     type Template;
     type Output;
 
-    fn new(template: &'a Self::Template) -> DevinitResult<RendererVariant>;
+    #[allow(clippy::new_ret_no_self)]
+    fn new(template: &'a Self::Template) -> DevinitResult<RendererVariant<'a>>;
 
-    fn add_variable<S: AsRef<str>>(&mut self, key: S, val: S);
+    fn add_variable<S: AsRef<str>, V: Serialize + ?Sized>(&mut self, key: S, val: &V);
     fn set_builtin_variables(&mut self, defs: &BuiltinVariables);
 
     fn render(&self) -> DevinitResult<Self::Output>;
@@ -50,7 +90,7 @@ impl<'a> Renderer<'a> for FileRenderer<'a> {
     type Output = String;
 
     /// Initialise a new renderer for the given file template
-    fn new(template: &'a Self::Template) -> DevinitResult<RendererVariant> {
+    fn new(template: &'a Self::Template) -> DevinitResult<RendererVariant<'a>> {
         Ok(RendererVariant::File(Self {
             ctx_ref: template.context(),
             template,
@@ -58,8 +98,8 @@ impl<'a> Renderer<'a> for FileRenderer<'a> {
         }))
     }
 
-    fn add_variable<S: AsRef<str>>(&mut self, key: S, val: S) {
-        self.var_context.insert(key.as_ref(), val.as_ref());
+    fn add_variable<S: AsRef<str>, V: Serialize + ?Sized>(&mut self, key: S, val: &V) {
+        self.var_context.insert(key.as_ref(), val);
     }
 
     fn set_builtin_variables(&mut self, defs: &BuiltinVariables) {
@@ -72,13 +112,65 @@ impl<'a> Renderer<'a> for FileRenderer<'a> {
             .lock()
             .unwrap()
             .tera()
-            .render(&self.template.name(), &self.var_context)
+            .render(self.template.name(), &self.var_context)
             .into_diagnostic()
             .map_err(|e| DevinitError::TemplateRenderError(format!("{:?}", e)))
     }
 
     fn template(&self) -> &Self::Template {
-        &self.template
+        self.template
+    }
+}
+
+/// Check that a rendered project output path is non-empty and stays within the project directory, returning it with
+/// surrounding whitespace trimmed.
+fn validate_output_path(path: &str) -> DevinitResult<String> {
+    let path = path.trim();
+    let escapes = Path::new(path)
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+
+    if path.is_empty() || escapes {
+        return Err(DevinitError::TemplateRenderError(format!(
+            "Output path \"{path}\" must be a relative path within the project directory\n"
+        )));
+    }
+    Ok(path.to_string())
+}
+
+/// Check if the rendered output of a condition template should be considered true.
+fn is_truthy(rendered: &str) -> bool {
+    !matches!(
+        rendered.trim().to_lowercase().as_str(),
+        "" | "false" | "0" | "no" | "off"
+    )
+}
+
+/// A single file output by a project template
+#[derive(Debug, Clone)]
+pub struct ProjectOutputFile {
+    pub contents: ProjectOutputContents,
+
+    /// Unix permission mode to give the file, if any
+    pub mode: Option<u32>,
+}
+
+/// The contents of a file output by a project template
+#[derive(Debug, Clone)]
+pub enum ProjectOutputContents {
+    /// Evaluated string output of a template
+    Text(String),
+
+    /// Bytes copied verbatim from an input file
+    Binary(Vec<u8>),
+}
+
+impl ProjectOutputContents {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Text(s) => s.as_bytes(),
+            Self::Binary(b) => b,
+        }
     }
 }
 
@@ -89,46 +181,109 @@ pub struct ProjectRenderer<'a> {
 
     template: &'a ProjectTemplate,
     var_context: tera::Context,
+
+    /// Directory the project will be written to, if any
+    output_dir: Option<PathBuf>,
+
+    /// Built-in variables shared by every file, which are extended with those specific to each file
+    builtins: Box<BuiltinVariables>,
 }
 
 impl<'a> Renderer<'a> for ProjectRenderer<'a> {
     type Template = ProjectTemplate;
-    type Output = HashMap<String, String>;
+    type Output = HashMap<String, ProjectOutputFile>;
 
-    fn new(template: &'a Self::Template) -> DevinitResult<RendererVariant> {
+    fn new(template: &'a Self::Template) -> DevinitResult<RendererVariant<'a>> {
         Ok(RendererVariant::Project(Self {
             ctx_ref: template.context(),
             template,
             var_context: Context::new(),
+            output_dir: None,
+            builtins: Box::default(),
         }))
     }
 
-    fn add_variable<S: AsRef<str>>(&mut self, key: S, val: S) {
-        self.var_context.insert(key.as_ref(), val.as_ref());
+    fn add_variable<S: AsRef<str>, V: Serialize + ?Sized>(&mut self, key: S, val: &V) {
+        self.var_context.insert(key.as_ref(), val);
     }
 
     fn set_builtin_variables(&mut self, defs: &BuiltinVariables) {
-        self.var_context.remove(&BUILTIN_VARIABLES_IDENT); // remove previous builtins
+        self.var_context.remove(BUILTIN_VARIABLES_IDENT); // remove previous builtins
         self.var_context.insert(BUILTIN_VARIABLES_IDENT, &defs);
+        *self.builtins = defs.clone();
     }
 
+    /// Render each file in the project, producing a map of output paths (relative to the project directory) to
+    /// evaluated output
     fn render(&self) -> DevinitResult<Self::Output> {
+        let ctx = self.ctx_ref.lock().unwrap();
+        let tera = ctx.tera();
+        let render = |id: &str| {
+            tera.render(id, &self.var_context)
+                .into_diagnostic()
+                .map_err(|e| DevinitError::TemplateRenderError(format!("{:?}", e)))
+        };
+
         let mut map = HashMap::new();
+        // configured paths of each file by its rendered output path, to find collisions
+        let mut sources = HashMap::<String, &String>::new();
+
+        // output paths are rendered first, as the built-in variables of each file depend on the others
+        let mut outputs = vec![];
+        for file in self.template.files() {
+            // skip the file if its condition evaluates to false
+            if let Some(when) = &file.when_template_name {
+                if !is_truthy(&render(when)?) {
+                    continue;
+                }
+            }
+            outputs.push((
+                file,
+                validate_output_path(&render(&file.path_template_name)?)?,
+            ));
+        }
+        let outpaths = outputs.iter().map(|(_, p)| p.as_str()).collect::<Vec<_>>();
+
+        for (file, outpath) in &outputs {
+            let contents = match &file.source {
+                ProjectFileSource::Template(id) | ProjectFileSource::FileTemplate(id) => {
+                    // each file gets the built-in variables for its own output path
+                    let mut var_context = self.var_context.clone();
+                    var_context.insert(
+                        BUILTIN_VARIABLES_IDENT,
+                        &self.file_builtins(outpath, &outpaths)?,
+                    );
+                    ProjectOutputContents::Text(
+                        tera.render(id, &var_context)
+                            .into_diagnostic()
+                            .map_err(|e| DevinitError::TemplateRenderError(format!("{:?}", e)))?,
+                    )
+                }
+                ProjectFileSource::Copy(src) => {
+                    ProjectOutputContents::Binary(fs::read(src).map_err(|e| {
+                        DevinitError::FileReadWriteError(format!(
+                            "Failed to read file {src:?}: {e}"
+                        ))
+                    })?)
+                }
+            };
+            if file.skip_if_empty && contents.as_bytes().trim_ascii().is_empty() {
+                continue;
+            }
+
+            if let Some(other) = sources.insert(outpath.clone(), &file.path) {
+                return Err(DevinitError::TemplateRenderError(format!(
+                    "Output paths \"{}\" and \"{}\" both render to \"{}\"\n",
+                    other, file.path, outpath
+                )));
+            }
 
-        for (outpath, _) in self.template.literals() {
-            // render each templated file
             map.insert(
                 outpath.clone(),
-                self.ctx_ref
-                    .lock()
-                    .unwrap()
-                    .tera()
-                    .render(
-                        format!("{}/{}", &self.template.name(), outpath).as_str(),
-                        &self.var_context,
-                    )
-                    .into_diagnostic()
-                    .map_err(|e| DevinitError::TemplateRenderError(format!("{:?}", e)))?,
+                ProjectOutputFile {
+                    contents,
+                    mode: file.mode,
+                },
             );
         }
 
@@ -136,6 +291,114 @@ impl<'a> Renderer<'a> for ProjectRenderer<'a> {
     }
 
     fn template(&self) -> &Self::Template {
-        &self.template
+        self.template
+    }
+}
+
+/// The outcome of evaluating a check to run before a project is written
+#[derive(Debug, Clone)]
+pub enum PreHookOutcome {
+    /// The check passed
+    Passed,
+
+    /// The check failed, with a message describing why
+    Failed(String),
+
+    /// The check is a command which must be run to find out if it passed, with a message to show if it fails
+    Command(String, String),
+}
+
+impl ProjectRenderer<'_> {
+    /// Set the directory the project will be written to, so that built-in variables can refer to existing files.
+    pub fn set_output_dir<P: AsRef<Path>>(&mut self, dir: P) {
+        self.output_dir = Some(dir.as_ref().to_path_buf());
+    }
+
+    /// Get the built-in variables for the file at `path`, relative to the project directory. `outpaths` are the paths
+    /// of every file in the project, so that project root markers can be found before they are written.
+    /// If there is no output directory (e.g. with --dry-run), they are taken from the relative path alone, and the
+    /// project root is found as if the project were written to the current directory.
+    fn file_builtins(&self, path: &str, outpaths: &[&str]) -> DevinitResult<BuiltinVariables> {
+        let name = |p: Option<&Path>| {
+            p.and_then(Path::file_name)
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default()
+        };
+
+        let mut builtins = *self.builtins.clone();
+        let dir = match &self.output_dir {
+            Some(dir) => {
+                let file = BuiltinVariables::for_output_path(dir.join(path))?;
+                builtins.set_file_name(file.file_name);
+                builtins.parent_name = file.parent_name;
+                builtins.file_contents = file.file_contents;
+                builtins.abs_path = file.abs_path;
+                builtins.project_name = name(Some(dir));
+                dir.clone()
+            }
+            None => {
+                builtins.set_file_name(name(Some(Path::new(path))));
+                builtins.parent_name = name(Path::new(path).parent());
+                env::current_dir().map_err(|e| {
+                    DevinitError::FileReadWriteError(format!("Cwd not accessible: {e}"))
+                })?
+            }
+        };
+
+        // markers written by the project count as well as existing ones, so that e.g. a new crate generated inside a
+        // repository is its own root, and the project directory is only used if there are no markers at all
+        let file = dir.join(path);
+        let is_output = |p: &Path| {
+            p.strip_prefix(&dir)
+                .is_ok_and(|rel| outpaths.iter().any(|o| Path::new(o) == rel))
+        };
+        let root = find_project_root_with(&file, is_output).unwrap_or_else(|| dir.clone());
+        builtins.set_root(Some(root.as_path()), file.strip_prefix(&root).unwrap());
+        builtins.path = path.to_string();
+
+        Ok(builtins)
+    }
+
+    /// Evaluate each check to run before the project is written, in order.
+    /// Commands are only rendered and not run, so that the caller can decide whether to run them.
+    pub fn render_pre_hooks(&self) -> DevinitResult<Vec<PreHookOutcome>> {
+        let ctx = self.ctx_ref.lock().unwrap();
+        let render = |id: &str| {
+            ctx.tera()
+                .render(id, &self.var_context)
+                .into_diagnostic()
+                .map_err(|e| DevinitError::TemplateRenderError(format!("{:?}", e)))
+        };
+
+        let mut ret = vec![];
+        for hook in self.template.pre_hooks() {
+            let message = match &hook.message_template_name {
+                Some(id) => render(id)?,
+                None => format!("\"{}\" did not pass", hook.source),
+            };
+
+            ret.push(match &hook.kind {
+                PreHookKind::Command(id) => PreHookOutcome::Command(render(id)?, message),
+                PreHookKind::Check(id) if is_truthy(&render(id)?) => PreHookOutcome::Passed,
+                PreHookKind::Check(_) => PreHookOutcome::Failed(message),
+            });
+        }
+
+        Ok(ret)
+    }
+
+    /// Render each post-generation hook command of the project, in order
+    pub fn render_post_hooks(&self) -> DevinitResult<Vec<String>> {
+        let ctx = self.ctx_ref.lock().unwrap();
+        self.template
+            .post_hooks()
+            .iter()
+            .map(|id| {
+                ctx.tera()
+                    .render(id, &self.var_context)
+                    .into_diagnostic()
+                    .map_err(|e| DevinitError::TemplateRenderError(format!("{:?}", e)))
+            })
+            .collect()
     }
 }
@@ -11,6 +11,7 @@ use miette::IntoDiagnostic;
 
 use crate::{
     error::{DevinitError, DevinitResult},
+    files::{split_front_matter, TemplateMeta},
     templater::{ContextArcMutex, FileRenderer, Renderer, RendererVariant},
 };
 
@@ -23,6 +24,8 @@ pub struct FileTemplate {
 
     name: String,
     source: String,
+
+    meta: TemplateMeta,
 }
 
 // When comparing file templates, we just want to compare them by name:
@@ -46,7 +49,8 @@ impl PartialOrd for FileTemplate {
 impl<'a> Template<'a> for FileTemplate {
     type Me = Self;
 
-    /// Load the file template from a single template configuration script
+    /// Load the file template from a single template configuration script, with any front matter block stripped from
+    /// its source
     fn load<P: AsRef<Path>>(path: P, ctx: ContextArcMutex) -> DevinitResult<Self::Me> {
         let name = String::from(path.as_ref().file_name().and_then(OsStr::to_str).ok_or(
             DevinitError::FileReadWriteError(format!(
@@ -56,11 +60,14 @@ impl<'a> Template<'a> for FileTemplate {
         )?);
         let literal = fs::read_to_string(&path)
             .map_err(|e| DevinitError::FileReadWriteError(e.to_string()))?;
+        let (meta, literal) = split_front_matter(&literal).map_err(|e| {
+            DevinitError::TemplateParseError(format!("In {:?}: {e}\n", path.as_ref()))
+        })?;
 
         let mut ctx_lock = ctx.lock().unwrap();
         ctx_lock
             .tera_mut()
-            .add_raw_template(&name, &literal)
+            .add_raw_template(&name, literal)
             .into_diagnostic()
             .map_err(|e| DevinitError::TemplateParseError(format!("{:?}", e)))?;
 
@@ -68,6 +75,7 @@ impl<'a> Template<'a> for FileTemplate {
             ctx_ref: ctx.clone(),
             name,
             source: path.as_ref().display().to_string(),
+            meta,
         })
     }
 
@@ -79,11 +87,15 @@ impl<'a> Template<'a> for FileTemplate {
         &self.source
     }
 
+    fn meta(&self) -> &TemplateMeta {
+        &self.meta
+    }
+
     fn context(&self) -> ContextArcMutex {
         self.ctx_ref.clone()
     }
 
-    fn make_renderer(&'a self) -> DevinitResult<RendererVariant> {
-        Ok(FileRenderer::new(&self)?)
+    fn make_renderer(&'a self) -> DevinitResult<RendererVariant<'a>> {
+        FileRenderer::new(self)
     }
 }
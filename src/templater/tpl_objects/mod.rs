@@ -17,7 +17,7 @@ pub use project_tpl::*;
 use core::fmt;
 use std::path::Path;
 
-use crate::error::DevinitResult;
+use crate::{error::DevinitResult, files::TemplateMeta};
 
 use super::{ContextArcMutex, RendererVariant};
 
@@ -29,7 +29,8 @@ pub trait Template<'a>: fmt::Debug + Clone + Ord {
 
     fn name(&self) -> &String;
     fn source(&self) -> &String;
+    fn meta(&self) -> &TemplateMeta;
 
     fn context(&self) -> ContextArcMutex;
-    fn make_renderer(&'a self) -> DevinitResult<RendererVariant>;
+    fn make_renderer(&'a self) -> DevinitResult<RendererVariant<'a>>;
 }
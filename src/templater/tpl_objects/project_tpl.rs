@@ -5,13 +5,18 @@
  *   See the LICENCE file for more information.
  */
 
-use std::{collections::HashMap, fs, path::Path};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
 
 use miette::IntoDiagnostic;
+use tera::ast::Node;
 
 use crate::{
     error::{DevinitError, DevinitResult},
-    files::ProjectTemplateYamlBuilder,
+    files::{ProjectTemplateYamlBuilder, TemplateMeta, VariableDecl},
     templater::{ContextArcMutex, ProjectRenderer, Renderer, RendererVariant},
 };
 
@@ -23,14 +28,114 @@ pub struct ProjectTemplate {
     ctx_ref: ContextArcMutex,
 
     name: String,
-    /// Hashmap of literals.
-    /// Key is the relative filename + folder structure to emit (e.g. foo/bar/baz.txt)
-    /// Value is the templated file literal
-    literals: HashMap<String, String>,
     source: String,
 
-    /// Names of each template file as can be found in the Tera instance
-    file_template_names: Vec<String>,
+    /// Each file to emit when rendering the project
+    files: Vec<ProjectFile>,
+
+    /// Checks to run before the project is written, in order
+    pre_hooks: Vec<PreHook>,
+
+    /// Names of the templates of each command to run after the project is written, in order
+    post_hooks: Vec<String>,
+
+    /// Id of the project template this one extends, if any
+    extends: Option<String>,
+
+    /// Map of output subdirectories to the ids of the project templates to emit into them
+    includes: BTreeMap<String, String>,
+
+    /// Configured output paths of inherited files that this template removes
+    removed_files: Vec<String>,
+
+    meta: TemplateMeta,
+}
+
+/// A single file to be emitted by a project template
+#[derive(Debug, Clone)]
+pub struct ProjectFile {
+    /// Output path as written in the project template config, relative to the output directory.
+    /// As this is itself a template (e.g. `src/{{ name }}.rs`), it must be rendered to get the actual output path.
+    pub path: String,
+
+    /// Name of the output path template as can be found in the Tera instance
+    pub path_template_name: String,
+
+    /// Where the contents of the file come from
+    pub source: ProjectFileSource,
+
+    /// Name of the template that decides if the file is emitted, if any
+    pub when_template_name: Option<String>,
+
+    /// If true, the file is not emitted if its rendered contents are only whitespace
+    pub skip_if_empty: bool,
+
+    /// Unix permission mode to give the output file, if any
+    pub mode: Option<u32>,
+}
+
+/// A check to run before a project is written
+#[derive(Debug, Clone)]
+pub struct PreHook {
+    pub kind: PreHookKind,
+
+    /// Name of the template of the message to show if the check fails, if any
+    pub message_template_name: Option<String>,
+
+    /// The command or expression as written in the project template config, for reporting failures
+    pub source: String,
+}
+
+impl PreHook {
+    /// Get the names of every template associated with this check, as can be found in the Tera instance
+    pub fn template_names(&self) -> Vec<&String> {
+        let mut ret = vec![match &self.kind {
+            PreHookKind::Command(id) | PreHookKind::Check(id) => id,
+        }];
+        if let Some(msg) = &self.message_template_name {
+            ret.push(msg);
+        }
+        ret
+    }
+}
+
+/// The kind of a check to run before a project is written
+#[derive(Debug, Clone)]
+pub enum PreHookKind {
+    /// Name of the template of a command, which passes if it exits successfully
+    Command(String),
+
+    /// Name of the template of a Tera expression, which passes if it evaluates to true
+    Check(String),
+}
+
+/// The source of the contents of a file emitted by a project template
+#[derive(Debug, Clone)]
+pub enum ProjectFileSource {
+    /// Name of the file contents template as can be found in the Tera instance
+    Template(String),
+
+    /// Absolute path of an input file to copy byte-for-byte
+    Copy(PathBuf),
+
+    /// Id of a file template to render
+    FileTemplate(String),
+}
+
+impl ProjectFile {
+    /// Get the names of every template associated with this file, as can be found in the Tera instance
+    pub fn template_names(&self) -> Vec<&String> {
+        let mut ret = vec![&self.path_template_name];
+        if let ProjectFileSource::Template(name) | ProjectFileSource::FileTemplate(name) =
+            &self.source
+        {
+            ret.push(name);
+        }
+        if let Some(when) = &self.when_template_name {
+            ret.push(when);
+        }
+        ret
+    }
 }
 
 // Similar to file templates, we just compare project templates by name:
@@ -68,33 +173,142 @@ impl<'a> Template<'a> for ProjectTemplate {
 
         let name = cfg_builder.name();
 
+        let removed_files = cfg
+            .files
+            .iter()
+            .filter(|(_, v)| v.is_none())
+            .map(|(k, _)| k.clone())
+            .collect();
+
         // load each referenced file in the project template as a literal
-        let mut file_template_names = vec![];
-        let mut literals = HashMap::new();
-        for (k, v) in cfg.files {
+        let mut files = vec![];
+        let mut outputs = HashSet::new();
+        for (k, v) in cfg_builder.expand_files(cfg.files)? {
+            if !outputs.insert(k.clone()) {
+                return Err(DevinitError::InvalidProjectConfigError(format!(
+                    "Output path \"{}\" in project template {} is mapped more than once",
+                    k, name
+                )));
+            }
+
             // try to load `v`, which will render to output path `k`.
-            let lit = fs::read_to_string(cfg_builder.folder().join(&v))
-                .map_err(|e| DevinitError::FileReadWriteError(e.to_string()))?;
-            literals.insert(k.clone(), lit.clone());
+            // files to be copied are only read when rendering, as they might be large or not valid text
+            let src_path = cfg_builder.folder().join(&v.source);
+            let lit = if v.copy || v.file_template.is_some() {
+                None
+            } else {
+                Some(
+                    fs::read_to_string(&src_path)
+                        .map_err(|e| DevinitError::FileReadWriteError(e.to_string()))?,
+                )
+            };
 
-            let id = format!("{}/{}", &name, &k);
+            let file = ProjectFile {
+                path_template_name: format!("{}/{}#path", &name, &k),
+                source: if let Some(id) = &v.file_template {
+                    ProjectFileSource::FileTemplate(id.clone())
+                } else if v.copy {
+                    ProjectFileSource::Copy(src_path.clone())
+                } else {
+                    ProjectFileSource::Template(format!("{}/{}", &name, &k))
+                },
+                when_template_name: v.when.as_ref().map(|_| format!("{}/{}#when", &name, &k)),
+                skip_if_empty: v.skip_if_empty,
+                mode: match v.file_template {
+                    Some(_) => v.mode,
+                    None => v.mode.or_else(|| source_mode(&src_path)),
+                },
+                path: k,
+            };
 
+            // the output path, file contents and condition are all registered as templates
             let mut ctx_lock = ctx.lock().unwrap();
-            ctx_lock
-                .tera_mut()
-                .add_raw_template(&id.as_str(), &lit)
-                .into_diagnostic()
-                .map_err(|e| DevinitError::TemplateParseError(format!("{:?}", e)))?;
+            let mut sources = vec![(&file.path_template_name, &file.path)];
+            if let (ProjectFileSource::Template(id), Some(lit)) = (&file.source, &lit) {
+                sources.push((id, lit));
+            }
+            if let (Some(id), Some(when)) = (&file.when_template_name, &v.when) {
+                sources.push((id, when));
+            }
+            for (id, lit) in sources {
+                ctx_lock
+                    .tera_mut()
+                    .add_raw_template(id, lit)
+                    .into_diagnostic()
+                    .map_err(|e| DevinitError::TemplateParseError(format!("{:?}", e)))?;
+            }
+
+            files.push(file);
+        }
+
+        // hook commands, check expressions and messages are templates too
+        let mut pre_hooks = vec![];
+        for (i, hook) in cfg.hooks.pre.into_iter().enumerate() {
+            let id = format!("{}#hooks.pre.{}", &name, i);
+            let (kind, source) = match (hook.run, hook.check) {
+                (Some(cmd), _) => {
+                    add_template(&ctx, &id, &cmd)?;
+                    (PreHookKind::Command(id.clone()), cmd)
+                }
+                (None, Some(expr)) => {
+                    // the expression is checked on its own first, as it could otherwise break out of the if tag
+                    validate_check(&expr).map_err(|e| {
+                        DevinitError::InvalidProjectConfigError(format!(
+                            "Bad check expression \"{}\" in hooks.pre.{} of {}:\n{}",
+                            expr,
+                            i,
+                            proj_dir.join("templaterc.yml").display(),
+                            e
+                        ))
+                    })?;
+                    let lit = format!("{{% if {expr} %}}true{{% else %}}false{{% endif %}}");
+                    add_template(&ctx, &id, &lit)?;
+                    (PreHookKind::Check(id.clone()), expr)
+                }
+                (None, None) => {
+                    unreachable!("pre hooks are checked to have a command or expression")
+                }
+            };
 
-            file_template_names.push(id);
+            let message_template_name = match hook.message {
+                Some(msg) => {
+                    let msg_id = format!("{id}#message");
+                    add_template(&ctx, &msg_id, &msg)?;
+                    Some(msg_id)
+                }
+                None => None,
+            };
+
+            pre_hooks.push(PreHook {
+                kind,
+                message_template_name,
+                source,
+            });
+        }
+
+        let mut post_hooks = vec![];
+        for (i, cmd) in cfg.hooks.post.iter().enumerate() {
+            let id = format!("{}#hooks.post.{}", &name, i);
+            add_template(&ctx, &id, cmd)?;
+            post_hooks.push(id);
         }
 
         Ok(Self {
             ctx_ref: ctx.clone(),
             name: cfg_builder.name().clone(),
-            literals,
             source: path.as_ref().display().to_string(),
-            file_template_names,
+            files,
+            pre_hooks,
+            post_hooks,
+            extends: cfg.extends,
+            includes: cfg.include,
+            removed_files,
+            meta: TemplateMeta {
+                description: cfg.description,
+                tags: cfg.tags,
+                variables: cfg.variables,
+                ..Default::default()
+            },
         })
     }
 
@@ -106,21 +320,170 @@ impl<'a> Template<'a> for ProjectTemplate {
         &self.source
     }
 
+    fn meta(&self) -> &TemplateMeta {
+        &self.meta
+    }
+
     fn context(&self) -> ContextArcMutex {
         self.ctx_ref.clone()
     }
 
-    fn make_renderer(&'a self) -> DevinitResult<RendererVariant> {
-        Ok(ProjectRenderer::new(&self)?)
+    fn make_renderer(&'a self) -> DevinitResult<RendererVariant<'a>> {
+        ProjectRenderer::new(self)
     }
 }
 
 impl ProjectTemplate {
-    pub fn file_template_names(&self) -> &Vec<String> {
-        &self.file_template_names
+    pub fn files(&self) -> &Vec<ProjectFile> {
+        &self.files
+    }
+
+    /// Get the id of the project template this one extends, if any
+    pub fn extends(&self) -> Option<&String> {
+        self.extends.as_ref()
+    }
+
+    /// Get the map of output subdirectories to the ids of the project templates to emit into them
+    pub fn includes(&self) -> &BTreeMap<String, String> {
+        &self.includes
+    }
+
+    /// Build the template resulting from applying this one over the (already composed) template it extends, `parent`,
+    /// and emitting each of its (already composed) included templates, `included`, into their subdirectories.
+    /// Files in this template replace any inherited ones with the same output path, and variable declarations replace
+    /// any inherited ones with the same name. Hooks are inherited from `parent`, but not from included templates.
+    pub fn compose(
+        &self,
+        parent: Option<&ProjectTemplate>,
+        included: &[(&String, &ProjectTemplate)],
+    ) -> DevinitResult<ProjectTemplate> {
+        let mut ret = self.clone();
+        let mut files = parent.map(|p| p.files.clone()).unwrap_or_default();
+        let mut variables = parent.map(|p| p.meta.variables.clone()).unwrap_or_default();
+
+        for (dir, tpl) in included {
+            for file in &tpl.files {
+                // included output paths must be registered as new templates under their subdirectory
+                let path = format!("{}/{}", dir.trim_end_matches('/'), file.path);
+                let path_template_name = format!("{}/{}#path", &self.name, &path);
+                add_template(&self.ctx_ref, &path_template_name, &path)?;
+                files.push(ProjectFile {
+                    path,
+                    path_template_name,
+                    ..file.clone()
+                });
+            }
+            variables.extend(tpl.meta.variables.iter().cloned());
+        }
+
+        files.retain(|f| !self.removed_files.contains(&f.path));
+        for file in &self.files {
+            files.retain(|f| f.path != file.path);
+            files.push(file.clone());
+        }
+        variables.extend(self.meta.variables.iter().cloned());
+
+        // later declarations replace earlier ones, in the position of the earliest
+        let mut decls: Vec<VariableDecl> = vec![];
+        for decl in variables {
+            match decls.iter_mut().find(|d| d.name == decl.name) {
+                Some(existing) => *existing = decl,
+                None => decls.push(decl),
+            }
+        }
+
+        if let Some(parent) = parent {
+            ret.pre_hooks = [parent.pre_hooks.clone(), self.pre_hooks.clone()].concat();
+            ret.post_hooks = [parent.post_hooks.clone(), self.post_hooks.clone()].concat();
+        }
+        ret.files = files;
+        ret.meta.variables = decls;
+
+        Ok(ret)
     }
 
-    pub fn literals(&self) -> &HashMap<String, String> {
-        &self.literals
+    /// Add the variable declarations in `decls` to the template, except for those it already declares.
+    pub fn add_variable_decls(&mut self, decls: Vec<VariableDecl>) {
+        for decl in decls {
+            if !self.meta.variables.iter().any(|d| d.name == decl.name) {
+                self.meta.variables.push(decl);
+            }
+        }
+    }
+
+    /// Get the checks to run before the project is written, in order
+    pub fn pre_hooks(&self) -> &Vec<PreHook> {
+        &self.pre_hooks
+    }
+
+    /// Get the names of the templates of each post-generation hook command, in order
+    pub fn post_hooks(&self) -> &Vec<String> {
+        &self.post_hooks
+    }
+}
+
+/// Register the template literal `lit` with id `id` in the Tera instance of `ctx`.
+fn add_template(ctx: &ContextArcMutex, id: &str, lit: &str) -> DevinitResult<()> {
+    ctx.lock()
+        .unwrap()
+        .tera_mut()
+        .add_raw_template(id, lit)
+        .into_diagnostic()
+        .map_err(|e| DevinitError::TemplateParseError(format!("{:?}", e)))
+}
+
+/// Check that `expr` is a single Tera expression, which can be used as the condition of an if tag.
+fn validate_check(expr: &str) -> Result<(), String> {
+    let template = tera::Template::new("check", None, &format!("{{{{ {expr} }}}}"))
+        .map_err(|e| e.to_string())?;
+    match template.ast.as_slice() {
+        [Node::VariableBlock(_, _)] => Ok(()),
+        _ => Err("the check is not a single expression".to_string()),
+    }
+}
+
+/// Get the Unix permission mode of the file at `path`, if it can be read.
+#[cfg(unix)]
+fn source_mode(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path)
+        .ok()
+        .map(|m| m.permissions().mode() & 0o7777)
+}
+
+/// Permission modes are only supported on Unix.
+#[cfg(not(unix))]
+fn source_mode(_path: &Path) -> Option<u32> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_checks() {
+        for expr in [
+            "name == \"ok\"",
+            "name is matching(\"^[a-z_]+$\")",
+            "not use_ci and items | length > 0",
+            "\"%}\" in name",
+        ] {
+            assert!(validate_check(expr).is_ok(), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn invalid_checks() {
+        for expr in [
+            "",
+            "name ==",
+            "true %}{% include \"other\" %}{% if true",
+            "a }}{{ b",
+            "name %}",
+        ] {
+            assert!(validate_check(expr).is_err(), "{}", expr);
+        }
     }
 }
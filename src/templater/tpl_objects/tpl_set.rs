@@ -6,7 +6,7 @@
  */
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     fs,
     marker::PhantomData,
     path::{Path, PathBuf},
@@ -20,12 +20,13 @@ use crate::{
     templater::{Context, ContextArcMutex},
 };
 
-use super::{FileTemplate, ProjectTemplate, Template};
+use super::{FileTemplate, ProjectFileSource, ProjectTemplate, Template};
 
 /// Templates are stored in hashsets via 'template set entries'.
-/// This way we can index templates by a value inside their structs, specifically their ID
+/// This way we can index templates by a value inside their structs, specifically their ID.
+/// The second member is the template location (directory) that the template was loaded from.
 #[derive(Debug, Clone)]
-struct TemplateSetEntry<'a, T: Template<'a>>(T, PhantomData<&'a T>);
+struct TemplateSetEntry<'a, T: Template<'a>>(T, PathBuf, PhantomData<&'a T>);
 
 impl<'a, T: Template<'a>> Eq for TemplateSetEntry<'a, T> {}
 impl<'a, T: Template<'a>> PartialEq<TemplateSetEntry<'a, T>> for TemplateSetEntry<'a, T> {
@@ -40,10 +41,20 @@ impl<'a, T: Template<'a>> std::hash::Hash for TemplateSetEntry<'a, T> {
 }
 impl<'a, T: Template<'a>> std::borrow::Borrow<str> for TemplateSetEntry<'a, T> {
     fn borrow(&self) -> &str {
-        &self.0.name()
+        self.0.name()
     }
 }
 
+/// A loaded template alongside the location it was found in, as presented when listing templates.
+#[derive(Debug)]
+pub struct TemplateListing<'b, T> {
+    pub template: &'b T,
+    pub location: &'b Path,
+
+    /// True if a template with the same id was found in a higher-priority location.
+    pub shadowed: bool,
+}
+
 /// A structure containing a set of each type of template available to the user.
 #[derive(Debug, Clone, Default)]
 pub struct TemplateSet<'a> {
@@ -51,6 +62,14 @@ pub struct TemplateSet<'a> {
 
     file_templates: HashSet<TemplateSetEntry<'a, FileTemplate>>,
     project_templates: HashSet<TemplateSetEntry<'a, ProjectTemplate>>,
+
+    /// Templates that were replaced by templates of the same id from a higher-priority location
+    shadowed_file_templates: Vec<TemplateSetEntry<'a, FileTemplate>>,
+    shadowed_project_templates: Vec<TemplateSetEntry<'a, ProjectTemplate>>,
+
+    /// Template locations, in the order they were loaded (ascending priority)
+    file_template_locations: Vec<PathBuf>,
+    project_template_locations: Vec<PathBuf>,
 }
 
 impl<'a> TemplateSet<'a> {
@@ -59,29 +78,143 @@ impl<'a> TemplateSet<'a> {
             ctx: Arc::new(Mutex::new(Context::new())),
             file_templates: HashSet::new(),
             project_templates: HashSet::new(),
+            shadowed_file_templates: vec![],
+            shadowed_project_templates: vec![],
+            file_template_locations: vec![],
+            project_template_locations: vec![],
         }
     }
 
+    /// Load all file templates in the directory `dir`.
+    /// Locations must be loaded in order of *ascending* priority: any template with the same id as one loaded from a
+    /// previous location will shadow it.
     pub fn load_file_templates<P: AsRef<Path>>(mut self, dir: P) -> DevinitResult<Self> {
-        let paths = Self::read_templates_dir(dir, false)?;
+        let paths = Self::read_templates_dir(&dir, false)?;
         let ctx = &self.ctx;
-        Self::load_templates_from_path_list(&mut self.file_templates, paths, |p| {
-            FileTemplate::load(p, ctx.clone())
-        })?;
+        Self::load_templates_from_path_list(
+            &mut self.file_templates,
+            &mut self.shadowed_file_templates,
+            dir.as_ref(),
+            paths,
+            |p| FileTemplate::load(p, ctx.clone()),
+        )?;
+        self.file_template_locations
+            .push(dir.as_ref().to_path_buf());
 
         Ok(self)
     }
 
+    /// Load all project templates in the directory `dir`.
+    /// Like file templates, locations must be loaded in order of ascending priority.
     pub fn load_project_templates<P: AsRef<Path>>(mut self, dir: P) -> DevinitResult<Self> {
-        let paths = Self::read_templates_dir(dir, true)?;
+        let paths = Self::read_templates_dir(&dir, true)?;
         let ctx = &self.ctx;
-        Self::load_templates_from_path_list(&mut self.project_templates, paths, |p| {
-            ProjectTemplate::load(p, ctx.clone())
-        })?;
+        Self::load_templates_from_path_list(
+            &mut self.project_templates,
+            &mut self.shadowed_project_templates,
+            dir.as_ref(),
+            paths,
+            |p| ProjectTemplate::load(p, ctx.clone()),
+        )?;
+        self.project_template_locations
+            .push(dir.as_ref().to_path_buf());
+
+        Ok(self)
+    }
+
+    /// Resolve the `extends` and `include` settings of each project template, replacing them with the composed
+    /// templates, and check that any file templates they refer to exist. This must be called after every location has
+    /// been loaded, as templates may refer to templates in other locations.
+    pub fn compose_project_templates(mut self) -> DevinitResult<Self> {
+        let mut composed = HashMap::new();
+        for entry in &self.project_templates {
+            self.compose_project_template(entry.0.name(), &mut composed, &mut vec![])?;
+        }
+
+        // file templates used by project templates bring their variable declarations with them
+        for t in composed.values_mut() {
+            let mut decls = vec![];
+            for file in t.files() {
+                if let ProjectFileSource::FileTemplate(id) = &file.source {
+                    let f = self.file_templates.get(id.as_str()).ok_or_else(|| {
+                        DevinitError::InvalidProjectConfigError(format!(
+                            "Project template \"{}\" refers to unknown file template \"{}\"",
+                            t.name(),
+                            id
+                        ))
+                    })?;
+                    decls.extend(f.0.meta().variables.iter().cloned());
+                }
+            }
+            t.add_variable_decls(decls);
+        }
+
+        self.project_templates = self
+            .project_templates
+            .into_iter()
+            .map(|e| {
+                let t = composed.remove(e.0.name()).unwrap();
+                TemplateSetEntry(t, e.1, PhantomData)
+            })
+            .collect();
 
         Ok(self)
     }
 
+    /// Compose the project template with id `name`, and each template it depends on, into `composed`.
+    /// `stack` holds the ids of the templates currently being composed, to detect cycles.
+    fn compose_project_template(
+        &self,
+        name: &str,
+        composed: &mut HashMap<String, ProjectTemplate>,
+        stack: &mut Vec<String>,
+    ) -> DevinitResult<()> {
+        if composed.contains_key(name) {
+            return Ok(());
+        }
+        if stack.iter().any(|s| s == name) {
+            stack.push(name.to_string());
+            return Err(DevinitError::InvalidProjectConfigError(format!(
+                "Project templates extend or include each other in a cycle: {}",
+                stack.join(" -> ")
+            )));
+        }
+
+        let template = &self
+            .project_templates
+            .get(name)
+            .ok_or_else(|| {
+                DevinitError::InvalidProjectConfigError(format!(
+                    "Project template \"{}\" refers to unknown project template \"{}\"",
+                    stack.last().map(String::as_str).unwrap_or_default(),
+                    name
+                ))
+            })?
+            .0;
+
+        // compose each dependency first
+        stack.push(name.to_string());
+        let deps = template
+            .extends()
+            .into_iter()
+            .chain(template.includes().values());
+        for dep in deps {
+            self.compose_project_template(dep, composed, stack)?;
+        }
+        stack.pop();
+
+        let parent = template.extends().map(|p| &composed[p]);
+        let included = template
+            .includes()
+            .iter()
+            .map(|(dir, id)| (dir, &composed[id]))
+            .collect::<Vec<_>>();
+        let t = template.compose(parent, &included)?;
+
+        composed.insert(name.to_string(), t);
+        Ok(())
+    }
+
     /// Recursively read through a directory for template config scripts
     fn read_templates_dir<P: AsRef<Path>>(path: P, projects: bool) -> DevinitResult<Vec<PathBuf>> {
         let mut buf = vec![];
@@ -100,10 +233,8 @@ impl<'a> TemplateSet<'a> {
                 let mut subdir = Self::read_templates_dir(entry.path(), projects)?;
                 buf.append(&mut subdir);
             }
-            if meta.is_file() {
-                if !projects || (entry.file_name() == "templaterc.yml") {
-                    buf.push(entry.path());
-                }
+            if meta.is_file() && (!projects || (entry.file_name() == "templaterc.yml")) {
+                buf.push(entry.path());
             }
         }
 
@@ -112,25 +243,37 @@ impl<'a> TemplateSet<'a> {
 
     /// Load each template in the list of file paths given in `paths`, each one referring to a template configuration script.
     /// The function to load a template is given via the `load_func` parameter.
+    /// Any previously-loaded templates with the same id as one in `location` are moved into `shadowed`.
     fn load_templates_from_path_list<
         P: AsRef<Path>,
         T: Template<'a>,
         F: Fn(&P) -> DevinitResult<T>,
     >(
         set: &mut HashSet<TemplateSetEntry<'a, T>>,
+        shadowed: &mut Vec<TemplateSetEntry<'a, T>>,
+        location: &Path,
         paths: Vec<P>,
         load_func: F,
     ) -> DevinitResult<()> {
-        Ok(for p in paths.iter() {
-            let t = TemplateSetEntry(load_func(p)?, PhantomData);
+        for p in paths.iter() {
+            let t = TemplateSetEntry(load_func(p)?, location.to_path_buf(), PhantomData);
+
+            if let Some(existing) = set.take(t.0.name().as_str()) {
+                if existing.1 == t.1 {
+                    // check against collisions, warn the user if there are multiple templates with the same name/id in
+                    // the same location
+                    error!("Found duplicate template id: \"{}\"\n", t.0.name());
+                    set.insert(existing);
+                    continue;
+                }
 
-            // check against collisions, warn the user if there are multiple templates with the same name/id
-            if set.contains(&t) {
-                error!("Found duplicate template id: \"{}\"\n", t.0.name());
-                continue;
+                // otherwise the existing template is from a lower-priority location, and is shadowed by this one
+                shadowed.push(existing);
             }
             set.insert(t);
-        })
+        }
+
+        Ok(())
     }
 
     /// Retrieve a file template from the set
@@ -154,13 +297,47 @@ impl<'a> TemplateSet<'a> {
             .0)
     }
 
-    /// Get a list of references to all loaded file templates
-    pub fn get_file_templates_all(&self) -> Vec<&FileTemplate> {
-        self.file_templates.iter().map(|e| &e.0).collect()
+    /// Get listings for all file templates found, including those that are shadowed
+    pub fn get_file_template_listings(&self) -> Vec<TemplateListing<'_, FileTemplate>> {
+        Self::make_listings(
+            &self.file_templates,
+            &self.shadowed_file_templates,
+            &self.file_template_locations,
+        )
+    }
+
+    /// Get listings for all project templates found, including those that are shadowed
+    pub fn get_project_template_listings(&self) -> Vec<TemplateListing<'_, ProjectTemplate>> {
+        Self::make_listings(
+            &self.project_templates,
+            &self.shadowed_project_templates,
+            &self.project_template_locations,
+        )
     }
 
-    /// Get a list of references to all loaded project templates
-    pub fn get_project_templates_all(&self) -> Vec<&ProjectTemplate> {
-        self.project_templates.iter().map(|e| &e.0).collect()
+    /// Build a list of template listings, sorted by location (in order of descending priority) and then by template id.
+    fn make_listings<'b, T: Template<'a>>(
+        set: &'b HashSet<TemplateSetEntry<'a, T>>,
+        shadowed: &'b [TemplateSetEntry<'a, T>],
+        locations: &[PathBuf],
+    ) -> Vec<TemplateListing<'b, T>> {
+        let mut ret = set
+            .iter()
+            .map(|e| (e, false))
+            .chain(shadowed.iter().map(|e| (e, true)))
+            .map(|(e, shadowed)| TemplateListing {
+                template: &e.0,
+                location: e.1.as_path(),
+                shadowed,
+            })
+            .collect::<Vec<_>>();
+
+        let priority = |l: &TemplateListing<T>| locations.iter().rposition(|p| p == l.location);
+        ret.sort_by(|a, b| {
+            priority(b)
+                .cmp(&priority(a))
+                .then(a.template.name().cmp(b.template.name()))
+        });
+        ret
     }
 }
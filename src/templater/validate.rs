@@ -0,0 +1,314 @@
+/*
+ *   Copyright (c) 2024 Jack Bennett.
+ *   All Rights Reserved.
+ *
+ *   See the LICENCE file for more information.
+ */
+
+use regex::Regex;
+use tera::{Map, Value};
+
+use crate::{
+    error::{DevinitError, DevinitResult},
+    files::{VariableDecl, VariableType},
+};
+
+/// Validate the variables in `vars` against the declarations in `decls`, filling in any declared defaults for variables
+/// that are not yet defined.
+/// Values are coerced into their declared types where possible (e.g. the string "true" into a boolean), so that they
+/// behave as expected in templates. Every violation is reported together in the returned error.
+pub fn validate_variables(
+    decls: &[VariableDecl],
+    vars: &mut Map<String, Value>,
+) -> DevinitResult<()> {
+    let mut violations = vec![];
+
+    for decl in decls {
+        let val = match vars.get(&decl.name).or(decl.default.as_ref()) {
+            Some(v) => v.clone(),
+            None => {
+                if decl.required {
+                    violations.push(format!("\"{}\" is required but was not defined", decl.name));
+                }
+                continue;
+            }
+        };
+
+        match check_value(decl, val) {
+            Ok(v) => {
+                vars.insert(decl.name.clone(), v);
+            }
+            Err(e) => violations.push(format!("\"{}\" {}", decl.name, e)),
+        }
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(DevinitError::InvalidVariablesError(
+            violations
+                .iter()
+                .map(|v| format!("  - {v}"))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        ))
+    }
+}
+
+/// Check a single value against its declaration, returning the value coerced into the declared type.
+/// On failure, a description of the violation is returned.
+pub fn check_value(decl: &VariableDecl, val: Value) -> Result<Value, String> {
+    let val = match decl.ty {
+        Some(ty) => coerce(ty, val)?,
+        None => val,
+    };
+
+    // each item of a list must be one of the choices
+    let items = match &val {
+        Value::Array(a) => a.iter().collect(),
+        v => vec![v],
+    };
+    let is_choice = |v: &Value| {
+        decl.choices
+            .iter()
+            .any(|c| scalar_string(c).is_some() && scalar_string(c) == scalar_string(v))
+    };
+    if (!decl.choices.is_empty() || decl.ty == Some(VariableType::Choice))
+        && !items.into_iter().all(is_choice)
+    {
+        return Err(format!(
+            "must be one of [{}], but was {}",
+            decl.choices
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            val
+        ));
+    }
+
+    if let Some(re) = &decl.regex {
+        let re = Regex::new(re).map_err(|e| format!("has an invalid regex declared: {e}"))?;
+        let s = scalar_string(&val).ok_or(format!("must be a string matching /{re}/"))?;
+        if !re.is_match(&s) {
+            return Err(format!("must match /{re}/, but was {val}"));
+        }
+    }
+
+    Ok(val)
+}
+
+/// Convert `val` into type `ty`, if it is not already.
+fn coerce(ty: VariableType, val: Value) -> Result<Value, String> {
+    match (ty, val) {
+        (VariableType::String | VariableType::Choice, v) => scalar_string(&v)
+            .map(Value::from)
+            .ok_or(format!("must be a string, but was {v}")),
+        (VariableType::Bool, Value::Bool(b)) => Ok(Value::Bool(b)),
+        (VariableType::Bool, Value::String(s)) => match s.to_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Ok(Value::Bool(true)),
+            "false" | "no" | "off" | "0" => Ok(Value::Bool(false)),
+            _ => Err(format!("must be a boolean, but was \"{s}\"")),
+        },
+        (VariableType::Int, Value::Number(n)) if n.is_i64() => Ok(Value::Number(n)),
+        (VariableType::Int, Value::String(s)) => s
+            .trim()
+            .parse::<i64>()
+            .map(Value::from)
+            .map_err(|_| format!("must be an integer, but was \"{s}\"")),
+        (VariableType::List, Value::Array(a)) => Ok(Value::Array(a)),
+        // lists can be given as comma-separated strings, e.g. on the command line
+        (VariableType::List, Value::String(s)) => Ok(Value::Array(
+            s.split(',')
+                .map(str::trim)
+                .filter(|i| !i.is_empty())
+                .map(Value::from)
+                .collect(),
+        )),
+        (VariableType::Bool, v) => Err(format!("must be a boolean, but was {v}")),
+        (VariableType::Int, v) => Err(format!("must be an integer, but was {v}")),
+        (VariableType::List, v) => Err(format!("must be a list, but was {v}")),
+    }
+}
+
+/// Get the string representation of a scalar value, or `None` if it is not a scalar.
+fn scalar_string(val: &Value) -> Option<String> {
+    match val {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn decl(ty: Option<VariableType>) -> VariableDecl {
+        VariableDecl {
+            name: "x".to_string(),
+            ty,
+            ..Default::default()
+        }
+    }
+
+    fn violations(decls: &[VariableDecl], vars: &mut Map<String, Value>) -> String {
+        match validate_variables(decls, vars) {
+            Err(DevinitError::InvalidVariablesError(msg)) => msg,
+            _ => panic!("expected variables {:?} to be invalid", vars),
+        }
+    }
+
+    #[test]
+    fn types() {
+        for (ty, val, expected) in [
+            (VariableType::String, json!("a"), json!("a")),
+            (VariableType::String, json!(3), json!("3")),
+            (VariableType::Bool, json!(true), json!(true)),
+            (VariableType::Bool, json!("Yes"), json!(true)),
+            (VariableType::Bool, json!("0"), json!(false)),
+            (VariableType::Int, json!(-4), json!(-4)),
+            (VariableType::Int, json!(" 12 "), json!(12)),
+            (VariableType::List, json!(["a"]), json!(["a"])),
+            (VariableType::List, json!("a, b,,c"), json!(["a", "b", "c"])),
+        ] {
+            assert_eq!(check_value(&decl(Some(ty)), val), Ok(expected));
+        }
+
+        for (ty, val) in [
+            (VariableType::String, json!(["a"])),
+            (VariableType::Bool, json!("maybe")),
+            (VariableType::Bool, json!(1)),
+            (VariableType::Int, json!("1.5")),
+            (VariableType::Int, json!(1.5)),
+            (VariableType::List, json!(true)),
+        ] {
+            assert!(
+                check_value(&decl(Some(ty)), val.clone()).is_err(),
+                "{:?} {}",
+                ty,
+                val
+            );
+        }
+
+        // untyped variables accept anything as it is
+        assert_eq!(
+            check_value(&decl(None), json!({"a": 1})),
+            Ok(json!({"a": 1}))
+        );
+    }
+
+    #[test]
+    fn choices() {
+        let d = VariableDecl {
+            choices: vec![json!("mit"), json!(2)],
+            ..decl(Some(VariableType::Choice))
+        };
+        assert_eq!(check_value(&d, json!("mit")), Ok(json!("mit")));
+        assert_eq!(check_value(&d, json!(2)), Ok(json!("2")));
+        assert!(check_value(&d, json!("gpl")).is_err());
+
+        // every item of a list must be a choice
+        let d = VariableDecl {
+            ty: Some(VariableType::List),
+            ..d
+        };
+        assert_eq!(check_value(&d, json!("mit,2")), Ok(json!(["mit", "2"])));
+        assert!(check_value(&d, json!(["mit", "gpl"])).is_err());
+    }
+
+    #[test]
+    fn regex() {
+        let d = VariableDecl {
+            regex: Some("^[a-z_]+$".to_string()),
+            ..decl(None)
+        };
+        assert_eq!(check_value(&d, json!("my_crate")), Ok(json!("my_crate")));
+        assert_eq!(
+            check_value(&d, json!("My-Crate")),
+            Err("must match /^[a-z_]+$/, but was \"My-Crate\"".to_string())
+        );
+        assert_eq!(
+            check_value(&d, json!(["a"])),
+            Err("must be a string matching /^[a-z_]+$/".to_string())
+        );
+
+        // the value is checked after being coerced into its type
+        let d = VariableDecl {
+            regex: Some("^[0-9]{2}$".to_string()),
+            ..decl(Some(VariableType::Int))
+        };
+        assert_eq!(check_value(&d, json!(" 42")), Ok(json!(42)));
+        assert!(check_value(&d, json!(420)).is_err());
+    }
+
+    #[test]
+    fn invalid_regex() {
+        let d = VariableDecl {
+            regex: Some("([a-z]".to_string()),
+            ..decl(None)
+        };
+        let e = check_value(&d, json!("a")).unwrap_err();
+        assert!(e.starts_with("has an invalid regex declared: "), "{}", e);
+    }
+
+    #[test]
+    fn required() {
+        let d = VariableDecl {
+            required: true,
+            ..decl(None)
+        };
+        let msg = violations(std::slice::from_ref(&d), &mut Map::new());
+        assert_eq!(msg, "  - \"x\" is required but was not defined");
+
+        let mut vars = Map::new();
+        vars.insert("x".to_string(), json!(1));
+        assert!(validate_variables(std::slice::from_ref(&d), &mut vars).is_ok());
+
+        // a default satisfies the requirement, and is filled in
+        let d = VariableDecl {
+            default: Some(json!("def")),
+            ..d
+        };
+        let mut vars = Map::new();
+        assert!(validate_variables(&[d], &mut vars).is_ok());
+        assert_eq!(vars.get("x"), Some(&json!("def")));
+
+        // optional variables are left undefined
+        let mut vars = Map::new();
+        assert!(validate_variables(&[decl(None)], &mut vars).is_ok());
+        assert!(vars.is_empty());
+    }
+
+    #[test]
+    fn all_violations_reported() {
+        let decls = [
+            VariableDecl {
+                name: "a".to_string(),
+                required: true,
+                ..Default::default()
+            },
+            VariableDecl {
+                name: "b".to_string(),
+                ty: Some(VariableType::Int),
+                ..Default::default()
+            },
+            VariableDecl {
+                name: "c".to_string(),
+                ty: Some(VariableType::Bool),
+                ..Default::default()
+            },
+        ];
+        let mut vars = Map::new();
+        vars.insert("b".to_string(), json!("two"));
+        vars.insert("c".to_string(), json!("on"));
+
+        assert_eq!(
+            violations(&decls, &mut vars),
+            "  - \"a\" is required but was not defined\n  - \"b\" must be an integer, but was \"two\""
+        );
+    }
+}
@@ -0,0 +1,316 @@
+/*
+ *   Copyright (c) 2024 Jack Bennett.
+ *   All Rights Reserved.
+ *
+ *   See the LICENCE file for more information.
+ */
+
+use std::{env, fs, path::Path};
+
+use tera::{Map, Value};
+
+use crate::error::{DevinitError, DevinitResult};
+
+/// Parse a variable definition given on the command line.
+/// `KEY=value` defines a string, whereas `KEY:=value` parses the value as YAML (and so JSON), allowing booleans,
+/// numbers, lists and maps to be defined.
+pub fn parse_define(s: &str) -> Result<(String, Value), String> {
+    let (key, val) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid KEY=value: no `=` found in `{s}`"))?;
+
+    let (key, val) = match key.strip_suffix(':') {
+        Some(key) => (
+            key,
+            serde_yaml::from_str(val).map_err(|e| format!("invalid value for `{key}`: {e}"))?,
+        ),
+        None => (key, Value::from(val)),
+    };
+
+    if key.split('.').any(str::is_empty) {
+        return Err(format!("invalid variable name `{key}`"));
+    }
+
+    Ok((key.to_string(), val))
+}
+
+/// Prefix of environment variables that define string variables.
+const ENV_VAR_PREFIX: &str = "DEVINIT_VAR_";
+
+/// Prefix of environment variables that define variables parsed as YAML (and so JSON).
+const ENV_YAML_PREFIX: &str = "DEVINIT_YAML_";
+
+/// Build a map of variables from the environment variables named `DEVINIT_VAR_<NAME>` (equivalent to `-D NAME=value`)
+/// and `DEVINIT_YAML_<NAME>` (equivalent to `-D NAME:=value`).
+/// As environment variable names cannot usually contain dots, `__` in a name is read as a dot.
+pub fn vars_from_env() -> DevinitResult<Map<String, Value>> {
+    let mut env_vars = env::vars_os()
+        .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+        .collect::<Vec<_>>();
+    env_vars.sort();
+
+    let mut defines = vec![];
+    let mut errors = vec![];
+    for (k, v) in env_vars {
+        let define = if let Some(name) = k.strip_prefix(ENV_VAR_PREFIX) {
+            format!("{}={v}", name.replace("__", "."))
+        } else if let Some(name) = k.strip_prefix(ENV_YAML_PREFIX) {
+            format!("{}:={v}", name.replace("__", "."))
+        } else {
+            continue;
+        };
+
+        match parse_define(&define) {
+            Ok(d) => defines.push(d),
+            Err(e) => errors.push(format!("  - environment variable {k}: {e}")),
+        }
+    }
+
+    if errors.is_empty() {
+        vars_from_defines(&defines)
+    } else {
+        Err(DevinitError::InvalidVariablesError(errors.join("\n")))
+    }
+}
+
+/// Build a map of variables from a list of definitions, in the order they were given.
+/// Dotted keys (e.g. `author.name`) define fields of nested objects, and a key given more than once defines a list of
+/// each of its values.
+pub fn vars_from_defines(defines: &[(String, Value)]) -> DevinitResult<Map<String, Value>> {
+    // group the values of repeated keys together first, so that they are not confused with values that are lists
+    let mut grouped: Vec<(&str, Vec<Value>)> = vec![];
+    for (k, v) in defines {
+        match grouped.iter_mut().find(|(key, _)| key == k) {
+            Some((_, vals)) => vals.push(v.clone()),
+            None => grouped.push((k, vec![v.clone()])),
+        }
+    }
+
+    let mut map = Map::new();
+    let mut conflicts = vec![];
+    for (key, mut vals) in grouped {
+        let val = match vals.len() {
+            1 => vals.remove(0),
+            _ => Value::Array(vals),
+        };
+        if !insert_dotted(&mut map, key, val) {
+            conflicts.push(format!(
+                "  - \"{key}\" conflicts with another definition, as they cannot both be a value and an object"
+            ));
+        }
+    }
+
+    if conflicts.is_empty() {
+        Ok(map)
+    } else {
+        Err(DevinitError::InvalidVariablesError(conflicts.join("\n")))
+    }
+}
+
+/// Read a map of variables from the file at `path`, which is parsed as TOML if it has a `.toml` extension, and otherwise
+/// as YAML (and so JSON).
+pub fn vars_from_file<P: AsRef<Path>>(path: P) -> DevinitResult<Map<String, Value>> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path).map_err(|e| {
+        DevinitError::FileReadWriteError(format!("Failed to read variables file {path:?}: {e}"))
+    })?;
+
+    let vars = if is_toml(path) {
+        toml::from_str(&contents).map_err(|e| e.to_string())
+    } else {
+        serde_yaml::from_str(&contents).map_err(|e| e.to_string())
+    };
+
+    vars.map_err(|e| {
+        DevinitError::InvalidVariablesError(format!(
+            "  - variables file {path:?} is not a map of variables: {e}"
+        ))
+    })
+}
+
+/// Write the map of variables `vars` to the file at `path`, in the format given by its extension: TOML for `.toml`,
+/// JSON for `.json` and otherwise YAML.
+pub fn save_vars<P: AsRef<Path>>(vars: &Map<String, Value>, path: P) -> DevinitResult<()> {
+    let path = path.as_ref();
+    let contents = if is_toml(path) {
+        toml::to_string(vars).map_err(|e| e.to_string())
+    } else if path.extension().is_some_and(|e| e == "json") {
+        serde_json::to_string_pretty(vars)
+            .map(|s| s + "\n")
+            .map_err(|e| e.to_string())
+    } else {
+        serde_yaml::to_string(vars).map_err(|e| e.to_string())
+    }
+    .map_err(|e| {
+        DevinitError::FileReadWriteError(format!("Failed to serialize variables for {path:?}: {e}"))
+    })?;
+
+    fs::write(path, contents).map_err(|e| {
+        DevinitError::FileReadWriteError(format!("Failed to write variables file {path:?}: {e}"))
+    })
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "toml")
+}
+
+/// Merge the variables in `over` into `base`, replacing those already defined.
+/// Objects defined in both are merged field by field, so that e.g. `author.name` can be set without losing the rest
+/// of a default `author` object.
+pub fn merge_vars(base: &mut Map<String, Value>, over: Map<String, Value>) {
+    for (k, v) in over {
+        match (base.get_mut(&k), v) {
+            (Some(Value::Object(b)), Value::Object(o)) => merge_vars(b, o),
+            (_, v) => {
+                base.insert(k, v);
+            }
+        }
+    }
+}
+
+/// Insert `val` into `map` under the dotted key `key`, creating intermediate objects as needed.
+/// Returns false if a part of the key is already defined as something other than an object.
+fn insert_dotted(map: &mut Map<String, Value>, key: &str, val: Value) -> bool {
+    match key.split_once('.') {
+        Some((head, rest)) => match map.entry(head).or_insert_with(|| Value::Object(Map::new())) {
+            Value::Object(inner) => insert_dotted(inner, rest, val),
+            _ => false,
+        },
+        None => match map.get_mut(key) {
+            Some(Value::Object(existing)) => match val {
+                Value::Object(o) => {
+                    merge_vars(existing, o);
+                    true
+                }
+                _ => false,
+            },
+            _ => {
+                map.insert(key.to_string(), val);
+                true
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tera::to_value;
+
+    fn defines(defs: &[&str]) -> Vec<(String, Value)> {
+        defs.iter().map(|d| parse_define(d).unwrap()).collect()
+    }
+
+    #[test]
+    fn parse_define_string() {
+        assert_eq!(
+            parse_define("name=a=b").unwrap(),
+            ("name".to_string(), Value::from("a=b"))
+        );
+        assert_eq!(
+            parse_define("flag=true").unwrap(),
+            ("flag".to_string(), Value::from("true"))
+        );
+    }
+
+    #[test]
+    fn parse_define_yaml() {
+        assert_eq!(parse_define("flag:=true").unwrap().1, Value::from(true));
+        assert_eq!(parse_define("n:=3").unwrap().1, Value::from(3));
+        assert_eq!(
+            parse_define("xs:=[1, \"two\"]").unwrap().1,
+            to_value((1, "two")).unwrap()
+        );
+        assert_eq!(
+            parse_define("author:={name: a}").unwrap().1,
+            to_value(HashMap::from([("name", "a")])).unwrap()
+        );
+        assert!(parse_define("xs:=[1").is_err());
+    }
+
+    #[test]
+    fn parse_define_invalid() {
+        assert!(parse_define("name").is_err());
+        assert!(parse_define("=value").is_err());
+        assert!(parse_define("author..name=a").is_err());
+        assert!(parse_define("author.=a").is_err());
+    }
+
+    #[test]
+    fn repeated_keys_make_a_list() {
+        let vars = vars_from_defines(&defines(&["x=1", "y=2", "x=3"])).unwrap();
+        assert_eq!(vars["x"], to_value(["1", "3"]).unwrap());
+        assert_eq!(vars["y"], Value::from("2"));
+    }
+
+    #[test]
+    fn repeated_list_values_are_not_flattened() {
+        let vars = vars_from_defines(&defines(&["x:=[1, 2]", "x:=[3]"])).unwrap();
+        assert_eq!(vars["x"], to_value([vec![1, 2], vec![3]]).unwrap());
+    }
+
+    #[test]
+    fn dotted_keys_make_objects() {
+        let vars = vars_from_defines(&defines(&[
+            "author.name=a",
+            "author.email=b",
+            "author.links.home=c",
+        ]))
+        .unwrap();
+        assert_eq!(vars["author"]["name"], Value::from("a"));
+        assert_eq!(vars["author"]["email"], Value::from("b"));
+        assert_eq!(vars["author"]["links"]["home"], Value::from("c"));
+    }
+
+    #[test]
+    fn dotted_keys_merge_with_objects() {
+        let vars = vars_from_defines(&defines(&["author:={name: a}", "author.email=b"])).unwrap();
+        assert_eq!(vars["author"]["name"], Value::from("a"));
+        assert_eq!(vars["author"]["email"], Value::from("b"));
+
+        let vars = vars_from_defines(&defines(&["author.email=b", "author:={name: a}"])).unwrap();
+        assert_eq!(vars["author"]["name"], Value::from("a"));
+        assert_eq!(vars["author"]["email"], Value::from("b"));
+    }
+
+    #[test]
+    fn value_and_object_conflict() {
+        for defs in [
+            ["author=a", "author.name=b"],
+            ["author.name=b", "author=a"],
+            ["author.name=b", "author.name.first=c"],
+        ] {
+            match vars_from_defines(&defines(&defs)) {
+                Err(DevinitError::InvalidVariablesError(msg)) => assert!(msg.contains("conflicts")),
+                res => panic!("expected a conflict for {:?}, got {:?}", defs, res),
+            }
+        }
+    }
+
+    #[test]
+    fn insert_dotted_conflict() {
+        let mut map = Map::new();
+        assert!(insert_dotted(&mut map, "a.b", Value::from(1)));
+        assert!(insert_dotted(&mut map, "a.c", Value::from(2)));
+        assert!(!insert_dotted(&mut map, "a.b.c", Value::from(3)));
+        assert!(!insert_dotted(&mut map, "a", Value::from(4)));
+        assert_eq!(
+            map["a"],
+            to_value(HashMap::from([("b", 1), ("c", 2)])).unwrap()
+        );
+    }
+
+    #[test]
+    fn merge_vars_merges_objects() {
+        let mut base =
+            vars_from_defines(&defines(&["author.name=a", "author.email=b", "x=1"])).unwrap();
+        merge_vars(
+            &mut base,
+            vars_from_defines(&defines(&["author.email=c", "x:=[2]"])).unwrap(),
+        );
+        assert_eq!(base["author"]["name"], Value::from("a"));
+        assert_eq!(base["author"]["email"], Value::from("c"));
+        assert_eq!(base["x"], to_value([2]).unwrap());
+    }
+}